@@ -5,10 +5,22 @@ use clap::Parser;
 use futures::stream::{StreamExt, TryStreamExt};
 use imop::compression;
 use imop::file::{File, Origin};
-use imop::image::{Format as ImageFormat, Image, Optimizations};
+use imop::headers::{Accept, HeaderMapExt};
+use imop::image::{
+    mime_of_format, Dpr, Format as ImageFormat, Image, Optimizations, OutputFormat, ResizeFilter,
+};
+use imop::info::Info;
+use imop::limits::Limits;
+use imop::metadata::MetadataPolicy;
+use imop::placeholder::Placeholder;
+use imop::quality::Ssim;
+use imop::watermark::{WatermarkSpec, Watermarks};
 use reqwest::Url;
 use serde::Deserialize;
+use std::path::PathBuf;
+use std::sync::Arc;
 use std::time::{Duration, Instant};
+use tokio::io::AsyncReadExt;
 use tokio::signal;
 use tokio_util::compat::FuturesAsyncReadCompatExt;
 use warp::{Filter, Rejection, Reply};
@@ -34,10 +46,91 @@ pub struct ImageSource {
     pub image: Option<reqwest::Url>,
 }
 
+#[derive(Deserialize, Debug, Clone)]
+pub struct PaletteQuery {
+    /// number of palette colors
+    pub colors: Option<usize>,
+}
+
 #[derive(Parser)]
 struct Options {
     #[clap(short = 'p', long = "port", default_value = "3000")]
     port: u16,
+
+    #[clap(
+        long = "filter",
+        default_value = "lanczos3",
+        help = "default resampling filter"
+    )]
+    filter: ResizeFilter,
+
+    #[clap(
+        long = "max-dpr",
+        default_value = "3",
+        help = "maximum device pixel ratio"
+    )]
+    max_dpr: Dpr,
+
+    #[clap(
+        long = "enlarge",
+        help = "scale images beyond their source size unless requested otherwise"
+    )]
+    enlarge: bool,
+
+    #[clap(
+        long = "min-ssim",
+        default_value = "0.98",
+        help = "default minimum similarity for quality=auto"
+    )]
+    min_ssim: Ssim,
+
+    #[clap(
+        long = "max-input-bytes",
+        help = "largest accepted source image in bytes"
+    )]
+    max_input_bytes: Option<u64>,
+
+    #[clap(long = "max-width", help = "largest accepted source width")]
+    max_width: Option<u32>,
+
+    #[clap(long = "max-height", help = "largest accepted source height")]
+    max_height: Option<u32>,
+
+    #[clap(long = "max-pixels", help = "largest accepted number of source pixels")]
+    max_pixels: Option<u64>,
+
+    #[clap(
+        long = "max-alloc",
+        help = "largest allocation for decoding a source image in bytes"
+    )]
+    max_alloc: Option<u64>,
+
+    #[clap(
+        long = "root",
+        default_value = ".",
+        help = "directory the watermark paths are relative to"
+    )]
+    root: PathBuf,
+
+    #[clap(
+        long = "watermark",
+        help = "watermark as id=path[;gravity=..][;margin=..][;opacity=..][;scale=..]"
+    )]
+    watermarks: Vec<WatermarkSpec>,
+}
+
+impl Options {
+    /// Decoding limits, using the defaults for limits that are not configured.
+    fn limits(&self) -> Limits {
+        let default = Limits::default();
+        Limits {
+            max_input_bytes: self.max_input_bytes.or(default.max_input_bytes),
+            max_width: self.max_width.or(default.max_width),
+            max_height: self.max_height.or(default.max_height),
+            max_pixels: self.max_pixels.or(default.max_pixels),
+            max_alloc: self.max_alloc.or(default.max_alloc),
+        }
+    }
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -45,6 +138,12 @@ pub enum Error {
     #[error("image error error: `{0}`")]
     Image(#[from] imop::image::Error),
 
+    #[error("watermark error: `{0}`")]
+    Watermark(#[from] imop::watermark::Error),
+
+    #[error("placeholder error: `{0}`")]
+    Placeholder(#[from] imop::placeholder::Error),
+
     #[error("fetch error: `{0}`")]
     Fetch(#[from] reqwest::Error),
     // #[error("cache error: `{0}`")]
@@ -53,48 +152,143 @@ pub enum Error {
 
 impl warp::reject::Reject for Error {}
 
+/// Responds to images exceeding the decoding limits with 413 or 422.
+async fn handle_rejection(err: Rejection) -> Result<impl warp::Reply, Rejection> {
+    match err.find::<Error>() {
+        Some(Error::Image(imop::image::Error::LimitExceeded(exceeded))) => Ok(
+            warp::reply::with_status(exceeded.to_string(), exceeded.limit.status_code()),
+        ),
+        _ => Err(err),
+    }
+}
+
+/// Downloads the source, reading at most one byte more than `max_bytes`
+/// such that larger sources fail the input limit without being buffered.
+async fn fetch(url: Url, max_bytes: Option<u64>) -> Result<Vec<u8>, Error> {
+    let now = Instant::now();
+    let res = reqwest::get(url.clone()).await?;
+    let mut buffer = Vec::new();
+    let mut reader = tokio::io::BufReader::new(
+        res.bytes_stream()
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))
+            .into_async_read()
+            .compat(),
+    );
+    let mut reader = reader.take(max_bytes.map_or(u64::MAX, |max| max.saturating_add(1)));
+    tokio::io::copy(&mut reader, &mut buffer).await;
+    imop::debug!("download of {} took {:?}", &url, now.elapsed());
+    Ok(buffer)
+}
+
+async fn serve_placeholder(
+    src: ImageSource,
+    options: Arc<Options>,
+) -> Result<impl warp::Reply, Rejection> {
+    match src.image {
+        Some(url) => {
+            let limits = options.limits();
+            let buffer = fetch(url, limits.max_input_bytes).await?;
+            let img = Image::from_bytes(buffer, &limits, MetadataPolicy::StripAll)
+                .map_err(Error::from)?;
+            let placeholder = img.placeholder().map_err(Error::from)?;
+            Ok(warp::reply::json(&placeholder))
+        }
+        None => Err(warp::reject::reject()),
+    }
+}
+
+async fn serve_palette(
+    src: ImageSource,
+    query: PaletteQuery,
+    options: Arc<Options>,
+) -> Result<impl warp::Reply, Rejection> {
+    match src.image {
+        Some(url) => {
+            let limits = options.limits();
+            let buffer = fetch(url, limits.max_input_bytes).await?;
+            let img = Image::from_bytes(buffer, &limits, MetadataPolicy::StripAll)
+                .map_err(Error::from)?;
+            let colors = query.colors.unwrap_or(imop::palette::DEFAULT_COLORS);
+            Ok(warp::reply::json(&img.palette(colors)))
+        }
+        None => Err(warp::reject::reject()),
+    }
+}
+
 async fn fetch_and_serve_file(
     optimizations: Optimizations,
     src: ImageSource,
+    accept: Option<Accept>,
+    options: Arc<Options>,
+    watermarks: Arc<Watermarks>,
     // cache: Arc<FileSystemImageCache<CacheKey>>,
     // cache: Arc<C>,
 ) -> Result<impl warp::Reply, Rejection> {
     // ) -> Result<File, Rejection> {
+    let optimizations = optimizations
+        .with_max_dpr(options.max_dpr)
+        .with_default_enlarge(options.enlarge)
+        .with_default_min_ssim(options.min_ssim);
     imop::debug!("source = {:?}", &src);
     imop::debug!("optimizations = {:?}", &optimizations);
 
     match src.image {
         Some(url) => {
-            let now = Instant::now();
-            let res = reqwest::get(url.clone()).await.map_err(Error::from)?;
-            // let data = res.bytes().await.map_err(Error::from)?;
-            let mut buffer = Vec::new();
-            let mut reader = tokio::io::BufReader::new(
-                res.bytes_stream()
-                    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))
-                    .into_async_read()
-                    .compat(),
-            );
-            tokio::io::copy(&mut reader, &mut buffer).await;
+            let limits = options.limits();
+            let buffer = fetch(url, limits.max_input_bytes).await?;
+            let file_size = buffer.len() as u64;
+            // info reports EXIF fields, which encoding may strip
+            let policy = match optimizations.info {
+                Some(true) => MetadataPolicy::KeepAll,
+                _ => optimizations.metadata.unwrap_or_default(),
+            };
+            let mut img = Image::from_bytes(buffer, &limits, policy).map_err(Error::from)?;
 
-            imop::debug!("download of {} took {:?}", &url, now.elapsed());
-
-            let img = Image::new(std::io::Cursor::new(&buffer)).map_err(Error::from);
-            let mut img = img?;
+            if optimizations.info == Some(true) {
+                let info = Info::new(&img, file_size);
+                return Ok(warp::reply::json(&info).into_response());
+            }
 
+            // the negotiated format is part of the optimizations and hence the cache key
+            let negotiated = optimizations.format == Some(OutputFormat::Auto);
+            let optimizations = optimizations.negotiate_format(
+                accept.as_ref(),
+                img.format(),
+                img.pixel_format().has_alpha,
+                // a single selected frame is encoded as a still image
+                img.is_animated() && optimizations.frame.is_none(),
+            );
             let target_format = optimizations
-                .format
+                .output_format()
                 .or(img.format())
                 .unwrap_or(ImageFormat::Jpeg);
 
-            img.resize(optimizations.bounds());
+            if let Some(frame) = optimizations.frame {
+                img.select_frame(frame).map_err(Error::from)?;
+            }
+            img.orient(optimizations.orientation());
+            let filter = optimizations.filter.unwrap_or(options.filter);
+            img.resize(optimizations.bounds(), filter)
+                .map_err(Error::from)?;
+            img.pad(
+                optimizations.bounds(),
+                optimizations.background(target_format),
+            )
+            .map_err(Error::from)?;
+            img.adjust(&optimizations.adjustments())
+                .map_err(Error::from)?;
+            img.filter(&optimizations.filters()).map_err(Error::from)?;
+            if let Some(id) = optimizations.watermark {
+                img.overlay(watermarks.get(id).map_err(Error::from)?);
+            }
 
             let mut buffer = Vec::new();
             let mut cursor = std::io::Cursor::new(buffer);
             // let mut writer = std::io::BufWriter::new(cursor);
 
-            let encoded = img
-                .encode_to(&mut cursor, target_format, optimizations.quality)
+            // the size shrinks if the image is scaled down to fit `max_bytes`
+            let size = img
+                .encode_to(&mut cursor, target_format, &optimizations)
                 .map_err(Error::from)?;
 
             // let file_stream = stream(file, (start, end), Some(buf_size));
@@ -105,6 +299,21 @@ async fn fetch_and_serve_file(
             // let body = warp::hyper::Body::wrap_stream(writer);
             let body = warp::hyper::body::Bytes::from(cursor.into_inner());
             let mut resp = warp::reply::Response::new(body.into());
+            if let Some(mime) = mime_of_format(target_format) {
+                resp.headers_mut()
+                    .typed_insert(imop::headers::ContentType::from(mime));
+            }
+            // effective dimensions, which differ from the requested ones if not enlarged
+            resp.headers_mut()
+                .insert("x-image-width", warp::http::HeaderValue::from(size.width));
+            resp.headers_mut()
+                .insert("x-image-height", warp::http::HeaderValue::from(size.height));
+            if negotiated {
+                resp.headers_mut().insert(
+                    warp::http::header::VARY,
+                    warp::http::HeaderValue::from_static("accept"),
+                );
+            }
             // warp::hyper::body::Body::from(writer).into());
             // warp::reply::Response::new(warp::hyper::body::Bytes::from(writer).into());
 
@@ -121,12 +330,22 @@ async fn fetch_and_serve_file(
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let options: Options = Options::parse();
+    let options = Arc::new(Options::parse());
+    let placeholder_options = options.clone();
+    let palette_options = options.clone();
+    let watermarks = Arc::new(Watermarks::load(&options.root, &options.watermarks)?);
+    let addr = ([0, 0, 0, 0], options.port);
     let image_endpoint = warp::path::end()
         .or(warp::head())
         .unify()
         .and(warp::query::<Optimizations>())
         .and(warp::query::<ImageSource>())
+        .and(
+            warp::header::headers_cloned()
+                .map(|headers: warp::http::HeaderMap| headers.typed_get()),
+        )
+        .and(warp::any().map(move || options.clone()))
+        .and(warp::any().map(move || watermarks.clone()))
         // .and(warp::any().map(move || cache_clone.clone()))
         .and_then(fetch_and_serve_file)
         .with(warp::wrap_fn(compression::auto(
@@ -138,7 +357,21 @@ async fn main() -> Result<()> {
         signal::ctrl_c().await.expect("shutdown server");
         println!("server shutting down");
     };
-    let addr = ([0, 0, 0, 0], options.port);
-    warp::serve(image_endpoint).run(addr).await;
+    let placeholder_endpoint = warp::path!("placeholder")
+        .and(warp::query::<ImageSource>())
+        .and(warp::any().map(move || placeholder_options.clone()))
+        .and_then(serve_placeholder);
+
+    let palette_endpoint = warp::path!("palette")
+        .and(warp::query::<ImageSource>())
+        .and(warp::query::<PaletteQuery>())
+        .and(warp::any().map(move || palette_options.clone()))
+        .and_then(serve_palette);
+
+    let routes = placeholder_endpoint
+        .or(palette_endpoint)
+        .or(image_endpoint)
+        .recover(handle_rejection);
+    warp::serve(routes).run(addr).await;
     Ok(())
 }
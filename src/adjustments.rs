@@ -0,0 +1,305 @@
+use crate::params::{deserialize_from_str, Hundredths};
+use image::{ColorType, DynamicImage};
+
+/// Largest accepted brightness, contrast, saturation and gamma factor.
+const MAX_FACTOR: f32 = 10.0;
+
+const SEPIA: [[f32; 3]; 3] = [
+    [0.393, 0.769, 0.189],
+    [0.349, 0.686, 0.168],
+    [0.272, 0.534, 0.131],
+];
+const LUMINANCE: [f32; 3] = [0.2126, 0.7152, 0.0722];
+
+/// Multiplier where 1 leaves the image unchanged.
+#[derive(Eq, PartialEq, Hash, Debug, Clone, Copy)]
+pub struct Factor(Hundredths);
+
+/// Gamma correction, where values above 1 brighten the midtones.
+#[derive(Eq, PartialEq, Hash, Debug, Clone, Copy)]
+pub struct Gamma(Hundredths);
+
+/// Rotation of hues in whole degrees, normalized to 0 up to 359.
+#[derive(Eq, PartialEq, Hash, Debug, Clone, Copy)]
+pub struct Hue(u16);
+
+impl Factor {
+    #[inline]
+    #[must_use]
+    pub fn value(self) -> f32 {
+        self.0.value()
+    }
+}
+
+impl Gamma {
+    #[inline]
+    #[must_use]
+    pub fn value(self) -> f32 {
+        self.0.value()
+    }
+}
+
+impl Hue {
+    #[inline]
+    #[must_use]
+    pub fn degrees(self) -> u16 {
+        self.0
+    }
+}
+
+impl std::str::FromStr for Factor {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Hundredths::parse(s, "factor", MAX_FACTOR).map(Self)
+    }
+}
+
+impl std::str::FromStr for Gamma {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let gamma = Hundredths::parse(s, "gamma", MAX_FACTOR)?;
+        if gamma.value() == 0.0 {
+            return Err(format!(
+                "invalid gamma `{}`, expected 0.01 to {}",
+                s, MAX_FACTOR
+            ));
+        }
+        Ok(Self(gamma))
+    }
+}
+
+impl std::str::FromStr for Hue {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let degrees: i32 = s
+            .trim()
+            .parse()
+            .map_err(|_| format!("invalid hue rotation `{}`, expected degrees", s))?;
+        // the remainder is below 360
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let degrees = degrees.rem_euclid(360) as u16;
+        Ok(Self(degrees))
+    }
+}
+
+deserialize_from_str!(Factor, Gamma, Hue);
+
+/// Color adjustments, applied in the order brightness, contrast, gamma,
+/// saturation, hue rotation, grayscale, sepia and invert.
+///
+/// The adjustments follow the CSS filter functions of the same names and
+/// leave the alpha channel unchanged.
+#[derive(Eq, PartialEq, Hash, Debug, Clone, Copy, Default)]
+pub struct Adjustments {
+    pub brightness: Option<Factor>,
+    pub contrast: Option<Factor>,
+    pub gamma: Option<Gamma>,
+    pub saturation: Option<Factor>,
+    pub hue: Option<Hue>,
+    pub grayscale: bool,
+    pub sepia: bool,
+    pub invert: bool,
+}
+
+impl Adjustments {
+    #[inline]
+    #[must_use]
+    pub fn is_identity(&self) -> bool {
+        self.brightness.is_none()
+            && self.contrast.is_none()
+            && self.gamma.is_none()
+            && self.saturation.is_none()
+            && self.hue.is_none()
+            && !self.grayscale
+            && !self.sepia
+            && !self.invert
+    }
+
+    /// Adjusts the colors, keeping the color type unless sepia toning
+    /// adds color to a grayscale image.
+    #[must_use]
+    pub fn apply(&self, image: DynamicImage) -> DynamicImage {
+        if self.is_identity() {
+            return image;
+        }
+        let color = match image.color() {
+            ColorType::L8 if self.sepia => ColorType::Rgb8,
+            ColorType::La8 if self.sepia => ColorType::Rgba8,
+            ColorType::L16 if self.sepia => ColorType::Rgb16,
+            ColorType::La16 if self.sepia => ColorType::Rgba16,
+            color => color,
+        };
+        let saturation = self
+            .saturation
+            .map(|factor| saturation_matrix(factor.value()));
+        let hue = self.hue.map(|hue| hue_matrix(hue.degrees()));
+        let mut rgba = image.into_rgba32f();
+        for pixel in rgba.pixels_mut() {
+            let mut rgb = [pixel[0], pixel[1], pixel[2]];
+            if let Some(brightness) = self.brightness {
+                rgb = rgb.map(|c| c * brightness.value());
+            }
+            if let Some(contrast) = self.contrast {
+                rgb = rgb.map(|c| (c - 0.5) * contrast.value() + 0.5);
+            }
+            if let Some(gamma) = self.gamma {
+                rgb = rgb.map(|c| c.clamp(0.0, 1.0).powf(gamma.value().recip()));
+            }
+            if let Some(ref saturation) = saturation {
+                rgb = multiply(saturation, rgb);
+            }
+            if let Some(ref hue) = hue {
+                rgb = multiply(hue, rgb);
+            }
+            if self.grayscale {
+                let luminance = dot(LUMINANCE, rgb);
+                rgb = [luminance; 3];
+            }
+            if self.sepia {
+                rgb = multiply(&SEPIA, rgb);
+            }
+            if self.invert {
+                rgb = rgb.map(|c| 1.0 - c.clamp(0.0, 1.0));
+            }
+            let [r, g, b] = rgb.map(|c| c.clamp(0.0, 1.0));
+            pixel.0 = [r, g, b, pixel[3]];
+        }
+        convert(DynamicImage::ImageRgba32F(rgba), color)
+    }
+}
+
+#[inline]
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+/// Multiplies the matrix with the color, clamping the input first.
+#[inline]
+fn multiply(matrix: &[[f32; 3]; 3], rgb: [f32; 3]) -> [f32; 3] {
+    let rgb = rgb.map(|c| c.clamp(0.0, 1.0));
+    matrix.map(|row| dot(row, rgb))
+}
+
+fn saturation_matrix(s: f32) -> [[f32; 3]; 3] {
+    [
+        [0.213 + 0.787 * s, 0.715 - 0.715 * s, 0.072 - 0.072 * s],
+        [0.213 - 0.213 * s, 0.715 + 0.285 * s, 0.072 - 0.072 * s],
+        [0.213 - 0.213 * s, 0.715 - 0.715 * s, 0.072 + 0.928 * s],
+    ]
+}
+
+fn hue_matrix(degrees: u16) -> [[f32; 3]; 3] {
+    let (sin, cos) = f32::from(degrees).to_radians().sin_cos();
+    [
+        [
+            0.213 + cos * 0.787 - sin * 0.213,
+            0.715 - cos * 0.715 - sin * 0.715,
+            0.072 - cos * 0.072 + sin * 0.928,
+        ],
+        [
+            0.213 - cos * 0.213 + sin * 0.143,
+            0.715 + cos * 0.285 + sin * 0.140,
+            0.072 - cos * 0.072 - sin * 0.283,
+        ],
+        [
+            0.213 - cos * 0.213 - sin * 0.787,
+            0.715 - cos * 0.715 + sin * 0.715,
+            0.072 + cos * 0.928 + sin * 0.072,
+        ],
+    ]
+}
+
+/// Converts the image to the given color type.
+fn convert(image: DynamicImage, color: ColorType) -> DynamicImage {
+    match color {
+        ColorType::L8 => DynamicImage::ImageLuma8(image.into_luma8()),
+        ColorType::La8 => DynamicImage::ImageLumaA8(image.into_luma_alpha8()),
+        ColorType::Rgb8 => DynamicImage::ImageRgb8(image.into_rgb8()),
+        ColorType::Rgba8 => DynamicImage::ImageRgba8(image.into_rgba8()),
+        ColorType::L16 => DynamicImage::ImageLuma16(image.into_luma16()),
+        ColorType::La16 => DynamicImage::ImageLumaA16(image.into_luma_alpha16()),
+        ColorType::Rgb16 => DynamicImage::ImageRgb16(image.into_rgb16()),
+        ColorType::Rgba16 => DynamicImage::ImageRgba16(image.into_rgba16()),
+        ColorType::Rgb32F => DynamicImage::ImageRgb32F(image.into_rgb32f()),
+        _ => image,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Adjustments, Gamma, Hue};
+    use image::{ColorType, DynamicImage, GrayImage, Luma, Rgba, RgbaImage};
+    use pretty_assertions::assert_eq;
+
+    fn pixel(color: [u8; 4]) -> DynamicImage {
+        DynamicImage::ImageRgba8(RgbaImage::from_pixel(1, 1, Rgba(color)))
+    }
+
+    fn adjust(adjustments: Adjustments, image: DynamicImage) -> [u8; 4] {
+        adjustments.apply(image).to_rgba8().get_pixel(0, 0).0
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!("370".parse::<Hue>().unwrap().degrees(), 10);
+        assert_eq!("-90".parse::<Hue>().unwrap().degrees(), 270);
+        assert!("0".parse::<Gamma>().is_err());
+        assert!("2.2".parse::<Gamma>().is_ok());
+    }
+
+    #[test]
+    fn adjustments() {
+        let red = pixel([200, 0, 0, 128]);
+        let grayscale = Adjustments {
+            grayscale: true,
+            ..Adjustments::default()
+        };
+        assert_eq!(adjust(grayscale, red.clone()), [43, 43, 43, 128]);
+
+        let invert = Adjustments {
+            invert: true,
+            ..Adjustments::default()
+        };
+        assert_eq!(adjust(invert, red.clone()), [55, 255, 255, 128]);
+
+        let hue = Adjustments {
+            hue: Some("120".parse().unwrap()),
+            ..Adjustments::default()
+        };
+        let [r, g, b, _] = adjust(hue, red.clone());
+        assert!(g > r && g > b);
+
+        let desaturate = Adjustments {
+            saturation: Some("0".parse().unwrap()),
+            ..Adjustments::default()
+        };
+        let [r, g, b, _] = adjust(desaturate, red);
+        assert!(r == g && g == b);
+    }
+
+    #[test]
+    fn order() {
+        // brightness is applied before inverting
+        let adjustments = Adjustments {
+            brightness: Some("0".parse().unwrap()),
+            invert: true,
+            ..Adjustments::default()
+        };
+        assert_eq!(adjust(adjustments, pixel([10, 20, 30, 255])), [255; 4]);
+    }
+
+    #[test]
+    fn sepia_colors_grayscale() {
+        let gray = DynamicImage::ImageLuma8(GrayImage::from_pixel(1, 1, Luma([128])));
+        let adjustments = Adjustments {
+            sepia: true,
+            ..Adjustments::default()
+        };
+        let toned = adjustments.apply(gray);
+        assert_eq!(toned.color(), ColorType::Rgb8);
+        assert_eq!(toned.to_rgba8().get_pixel(0, 0).0, [173, 154, 120, 255]);
+    }
+}
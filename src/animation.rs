@@ -0,0 +1,133 @@
+use super::image::Format;
+use image::{AnimationDecoder, Delay, DynamicImage, ImageError};
+use std::io::{BufRead, Seek, SeekFrom, Write};
+
+/// Frame of an animated image covering the full canvas.
+///
+/// The image is a `DynamicImage` unless a backend decodes frames into its own type.
+#[derive(Debug, Clone)]
+pub struct Frame<I = DynamicImage> {
+    pub image: I,
+    /// time the frame is displayed
+    pub delay: Delay,
+}
+
+impl From<image::Frame> for Frame {
+    #[inline]
+    fn from(frame: image::Frame) -> Self {
+        let delay = frame.delay();
+        Self {
+            image: DynamicImage::ImageRgba8(frame.into_buffer()),
+            delay,
+        }
+    }
+}
+
+impl From<Frame> for image::Frame {
+    #[inline]
+    fn from(frame: Frame) -> Self {
+        image::Frame::from_parts(frame.image.into_rgba8(), 0, 0, frame.delay)
+    }
+}
+
+/// Decodes all frames of a GIF, or an animated WebP if the `webp` feature is enabled.
+///
+/// Returns `None` for other formats. Still WebP images have no frames.
+/// The reader is rewound to its initial position afterwards. Decoding fails
+/// with a limit error once the decoded frames exceed `max_alloc` bytes, which
+/// is checked from the container before decoding WebP animations.
+pub fn decode<R: BufRead + Seek>(
+    reader: &mut R,
+    format: Option<Format>,
+    max_alloc: Option<u64>,
+) -> Result<Option<Vec<Frame>>, ImageError> {
+    use image::codecs::gif::GifDecoder;
+    let start = reader.stream_position()?;
+    let frames: Result<Vec<Frame>, _> = match format {
+        Some(Format::Gif) => {
+            let mut allocated = 0;
+            GifDecoder::new(&mut *reader)?
+                .into_frames()
+                .map(|frame| {
+                    let frame = Frame::from(frame?);
+                    allocated += frame.image.as_bytes().len() as u64;
+                    check_alloc(allocated, max_alloc)?;
+                    Ok(frame)
+                })
+                .collect()
+        }
+        // the animation decoder of the `image` crate fails for lossy frames
+        #[cfg(feature = "webp")]
+        Some(Format::WebP) => {
+            let mut data = Vec::new();
+            reader.read_to_end(&mut data)?;
+            check_alloc(crate::codecs::webp::animation_alloc(&data), max_alloc)
+                .and_then(|_| crate::codecs::webp::decode_animation(&data))
+        }
+        _ => return Ok(None),
+    };
+    reader.seek(SeekFrom::Start(start))?;
+    frames.map(Some)
+}
+
+#[inline]
+fn check_alloc(allocated: u64, max_alloc: Option<u64>) -> Result<(), ImageError> {
+    use image::error::{LimitError, LimitErrorKind};
+    match max_alloc {
+        Some(max_alloc) if allocated > max_alloc => Err(ImageError::Limits(LimitError::from_kind(
+            LimitErrorKind::InsufficientMemory,
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Encodes the frames as an infinitely looping GIF.
+pub fn encode_gif<W: Write>(frames: &[Frame], w: &mut W) -> Result<(), ImageError> {
+    use image::codecs::gif::{GifEncoder, Repeat};
+    let mut encoder = GifEncoder::new(w);
+    encoder.set_repeat(Repeat::Infinite)?;
+    encoder.encode_frames(frames.iter().cloned().map(image::Frame::from))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::Frame;
+    use image::{Delay, DynamicImage, Rgba, RgbaImage};
+    use pretty_assertions::assert_eq;
+
+    /// Animation of solid frames in the given colors, 100ms each
+    pub(crate) fn frames(width: u32, height: u32, colors: &[[u8; 4]]) -> Vec<Frame> {
+        colors
+            .iter()
+            .map(|&color| Frame {
+                image: DynamicImage::ImageRgba8(RgbaImage::from_pixel(width, height, Rgba(color))),
+                delay: Delay::from_numer_denom_ms(100, 1),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn gif_roundtrip() {
+        let frames = frames(4, 2, &[[255, 0, 0, 255], [0, 0, 255, 255]]);
+        let mut encoded = std::io::Cursor::new(Vec::new());
+        super::encode_gif(&frames, &mut encoded).unwrap();
+
+        encoded.set_position(0);
+        let decoded = super::decode(&mut encoded, Some(image::ImageFormat::Gif), None)
+            .unwrap()
+            .unwrap();
+        assert_eq!(encoded.position(), 0);
+        assert_eq!(decoded.len(), 2);
+        for (decoded, frame) in decoded.iter().zip(frames.iter()) {
+            assert_eq!(decoded.delay, frame.delay);
+            assert_eq!(decoded.image.to_rgba8(), frame.image.to_rgba8());
+        }
+    }
+
+    #[test]
+    fn other_formats() {
+        let mut encoded = std::io::Cursor::new(Vec::new());
+        let decoded = super::decode(&mut encoded, Some(image::ImageFormat::Png), None).unwrap();
+        assert!(decoded.is_none());
+    }
+}
@@ -0,0 +1,336 @@
+use super::{ImageBackend, Input, Output, PixelFormat};
+use crate::adjustments::Adjustments;
+use crate::animation::{self, Frame};
+use crate::bounds::{Rect, Size};
+use crate::codecs::jpeg;
+use crate::color::Color;
+use crate::filters::Filters;
+use crate::image::{Error, Format, Optimizations, ResizeFilter};
+use crate::limits::Limits;
+use crate::orientation::Orientation;
+use crate::palette::Palette;
+use crate::placeholder::{self, Placeholder};
+use crate::quality;
+use crate::smartcrop;
+use crate::watermark::Watermark;
+use image::io::Reader as ImageReader;
+use image::DynamicImage;
+use std::io::SeekFrom;
+
+/// Downscaling by more than this factor first shrinks the image with a cheap
+/// integer filter before the final high quality pass.
+const PRE_SHRINK_FACTOR: u32 = 4;
+
+/// Backend based on the `image` crate and the encoders of the `codecs` module.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ImageCrate;
+
+impl ImageBackend for ImageCrate {
+    type Image = DynamicImage;
+
+    fn dimensions(&self, reader: &mut dyn Input) -> Result<(Option<Format>, Size), Error> {
+        let start = reader.stream_position()?;
+        let header = ImageReader::new(&mut *reader).with_guessed_format()?;
+        let format = header.format();
+        let (width, height) = header.into_dimensions()?;
+        reader.seek(SeekFrom::Start(start))?;
+        Ok((format, Size { width, height }))
+    }
+
+    fn decode(&self, reader: &mut dyn Input, limits: &Limits) -> Result<DynamicImage, Error> {
+        let mut decoder = ImageReader::new(reader).with_guessed_format()?;
+        let mut decoder_limits = image::io::Limits::no_limits();
+        decoder_limits.max_alloc = limits.max_alloc;
+        decoder.limits(decoder_limits);
+        decoder.decode().map_err(|err| Error::decoding(err, limits))
+    }
+
+    fn decode_frames(
+        &self,
+        // the decoders expect sized readers
+        mut reader: &mut dyn Input,
+        format: Option<Format>,
+        limits: &Limits,
+    ) -> Result<Option<Vec<Frame>>, Error> {
+        animation::decode(&mut reader, format, limits.max_alloc)
+            .map_err(|err| Error::decoding(err, limits))
+    }
+
+    fn size(&self, image: &DynamicImage) -> Size {
+        Size {
+            width: image.width(),
+            height: image.height(),
+        }
+    }
+
+    fn pixel_format(&self, image: &DynamicImage) -> PixelFormat {
+        let color = image.color();
+        PixelFormat {
+            color_type: format!("{:?}", color).to_ascii_lowercase(),
+            bit_depth: color.bits_per_pixel() / u16::from(color.channel_count()),
+            bytes_per_pixel: color.bytes_per_pixel().into(),
+            has_alpha: color.has_alpha(),
+            has_color: color.has_color(),
+        }
+    }
+
+    fn resize(&self, image: DynamicImage, size: Size, filter: ResizeFilter) -> DynamicImage {
+        let pre_shrink = filter != ResizeFilter::Nearest
+            && size.width.saturating_mul(PRE_SHRINK_FACTOR) < image.width()
+            && size.height.saturating_mul(PRE_SHRINK_FACTOR) < image.height();
+        let image = if pre_shrink {
+            image.thumbnail_exact(size.width * 2, size.height * 2)
+        } else {
+            image
+        };
+        image.resize_exact(size.width, size.height, filter.into())
+    }
+
+    fn crop(&self, image: DynamicImage, window: Rect) -> DynamicImage {
+        image.crop_imm(window.x, window.y, window.width, window.height)
+    }
+
+    fn salient_window(&self, image: &DynamicImage, size: Size) -> Rect {
+        smartcrop::window(image, size)
+    }
+
+    fn orient(&self, image: DynamicImage, orientation: Orientation) -> DynamicImage {
+        orientation.apply(image)
+    }
+
+    fn pad(
+        &self,
+        image: DynamicImage,
+        canvas: Size,
+        window: Rect,
+        background: Color,
+    ) -> DynamicImage {
+        let opaque = !image.color().has_alpha() && background.is_opaque();
+        let mut padded = DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(
+            canvas.width,
+            canvas.height,
+            background.into(),
+        ));
+        image::imageops::overlay(
+            &mut padded,
+            &image,
+            i64::from(window.x),
+            i64::from(window.y),
+        );
+        if opaque {
+            DynamicImage::ImageRgb8(padded.into_rgb8())
+        } else {
+            padded
+        }
+    }
+
+    fn flatten(&self, image: &DynamicImage, background: Color) -> DynamicImage {
+        background.flatten(image)
+    }
+
+    fn adjust(&self, image: DynamicImage, adjustments: &Adjustments) -> DynamicImage {
+        adjustments.apply(image)
+    }
+
+    fn filter(&self, image: DynamicImage, filters: &Filters) -> DynamicImage {
+        filters.apply(image)
+    }
+
+    fn overlay(&self, image: DynamicImage, watermark: &Watermark) -> DynamicImage {
+        watermark.apply(image)
+    }
+
+    fn palette(&self, image: &DynamicImage, colors: usize) -> Palette {
+        Palette::new(image, colors)
+    }
+
+    fn placeholder(&self, image: &DynamicImage) -> Result<Placeholder, placeholder::Error> {
+        Placeholder::new(image)
+    }
+
+    fn similarity(
+        &self,
+        reference: &DynamicImage,
+        encoded: &[u8],
+        format: Format,
+    ) -> Result<f64, Error> {
+        let decoded = image::load_from_memory_with_format(encoded, format)?;
+        Ok(quality::ssim(reference, &decoded))
+    }
+
+    fn encode(
+        &self,
+        image: &DynamicImage,
+        // encoders expecting sized writers borrow the reference mutably
+        mut w: &mut dyn Output,
+        format: Format,
+        optimizations: &Optimizations,
+    ) -> Result<(), Error> {
+        use image::{codecs, ImageEncoder, ImageOutputFormat};
+        let quality = optimizations.quality();
+        if format == Format::Png && optimizations.optimize.unwrap_or(false) {
+            return crate::codecs::png::encode_optimized(image, &mut w).map_err(Error::from);
+        }
+        #[cfg(feature = "webp")]
+        if format == Format::WebP {
+            let quality = quality.unwrap_or(crate::codecs::webp::DEFAULT_QUALITY);
+            let lossless = optimizations.lossless.unwrap_or(false);
+            return crate::codecs::webp::encode(image, &mut w, quality, lossless)
+                .map_err(Error::from);
+        }
+        #[cfg(feature = "avif")]
+        if format == Format::Avif {
+            use crate::codecs::avif;
+            let quality = quality.unwrap_or(avif::DEFAULT_QUALITY);
+            let speed = optimizations.speed.unwrap_or(avif::DEFAULT_SPEED);
+            return avif::encode(image, &mut w, quality, speed).map_err(Error::from);
+        }
+        let data = image.as_bytes();
+        let color = image.color();
+        let width = image.width();
+        let height = image.height();
+        match format.into() {
+            ImageOutputFormat::Png => codecs::png::PngEncoder::new(w)
+                .write_image(data, width, height, color)
+                .map_err(Error::from),
+            ImageOutputFormat::Jpeg(_) => {
+                let settings = jpeg::Settings {
+                    quality: quality.unwrap_or(jpeg::DEFAULT_QUALITY),
+                    progressive: optimizations.progressive.unwrap_or(false),
+                    subsampling: optimizations.subsampling.unwrap_or_default(),
+                };
+                // JPEG has no alpha channel, transparent pixels would turn black
+                let flattened;
+                let image = if image.color().has_alpha() {
+                    flattened = optimizations.background(format).flatten(image);
+                    &flattened
+                } else {
+                    image
+                };
+                jpeg::default_encoder()
+                    .encode(image, w, settings)
+                    .map_err(Error::from)
+            }
+            ImageOutputFormat::Gif => codecs::gif::GifEncoder::new(w)
+                .encode(data, width, height, color)
+                .map_err(Error::from),
+            ImageOutputFormat::Ico => codecs::ico::IcoEncoder::new(w)
+                .write_image(data, width, height, color)
+                .map_err(Error::from),
+            ImageOutputFormat::Bmp => codecs::bmp::BmpEncoder::new(&mut w)
+                .write_image(data, width, height, color)
+                .map_err(Error::from),
+            ImageOutputFormat::Tiff => codecs::tiff::TiffEncoder::new(w)
+                .write_image(data, width, height, color)
+                .map_err(Error::from),
+            ImageOutputFormat::Unsupported(msg) => {
+                Err(Error::from(image::error::ImageError::Unsupported(
+                    image::error::UnsupportedError::from_format_and_kind(
+                        image::error::ImageFormatHint::Unknown,
+                        image::error::UnsupportedErrorKind::Format(
+                            image::error::ImageFormatHint::Name(msg),
+                        ),
+                    ),
+                )))
+            }
+            _ => Err(Error::from(image::error::ImageError::Unsupported(
+                image::error::UnsupportedError::from_format_and_kind(
+                    image::error::ImageFormatHint::Unknown,
+                    image::error::UnsupportedErrorKind::Format(
+                        image::error::ImageFormatHint::Name("missing format".to_string()),
+                    ),
+                ),
+            ))),
+        }
+    }
+
+    fn encode_frames(
+        &self,
+        frames: &[Frame],
+        mut w: &mut dyn Output,
+        format: Format,
+        optimizations: &Optimizations,
+    ) -> Result<(), Error> {
+        if format == Format::Gif {
+            return animation::encode_gif(frames, &mut w).map_err(Error::from);
+        }
+        #[cfg(feature = "webp")]
+        if format == Format::WebP {
+            let quality = optimizations
+                .quality()
+                .unwrap_or(crate::codecs::webp::DEFAULT_QUALITY);
+            let lossless = optimizations.lossless.unwrap_or(false);
+            return crate::codecs::webp::encode_animation(frames, &mut w, quality, lossless)
+                .map_err(Error::from);
+        }
+        match frames.first() {
+            Some(frame) => self.encode(&frame.image, w, format, optimizations),
+            None => Err(Error::from(image::error::ImageError::Parameter(
+                image::error::ParameterError::from_kind(
+                    image::error::ParameterErrorKind::NoMoreData,
+                ),
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ImageCrate;
+    use crate::backends::ImageBackend;
+    use crate::bounds::{Rect, Size};
+    use crate::image::{Format, Optimizations, ResizeFilter};
+    use crate::limits::Limits;
+    use image::{DynamicImage, GenericImageView, Rgba, RgbaImage};
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn roundtrip() {
+        let image = DynamicImage::ImageRgba8(RgbaImage::from_fn(40, 20, |x, _| {
+            Rgba(if x < 20 {
+                [255, 0, 0, 255]
+            } else {
+                [0, 0, 255, 255]
+            })
+        }));
+        let optimizations: Optimizations = serde_urlencoded::from_str("").unwrap();
+        let mut encoded = std::io::Cursor::new(Vec::new());
+        ImageCrate
+            .encode(&image, &mut encoded, Format::Png, &optimizations)
+            .unwrap();
+
+        encoded.set_position(0);
+        let (format, size) = ImageCrate.dimensions(&mut encoded).unwrap();
+        assert_eq!(format, Some(Format::Png));
+        assert_eq!(
+            size,
+            Size {
+                width: 40,
+                height: 20
+            }
+        );
+        assert_eq!(encoded.position(), 0);
+        let decoded = ImageCrate.decode(&mut encoded, &Limits::default()).unwrap();
+        assert_eq!(decoded, image);
+
+        // shrinking by more than the pre-shrink factor still yields the exact size
+        let resized = ImageCrate.resize(
+            decoded,
+            Size {
+                width: 4,
+                height: 2,
+            },
+            ResizeFilter::default(),
+        );
+        assert_eq!(resized.dimensions(), (4, 2));
+        let window = Rect {
+            x: 2,
+            y: 0,
+            width: 2,
+            height: 2,
+        };
+        let cropped = ImageCrate.crop(resized, window);
+        assert_eq!(cropped.dimensions(), (2, 2));
+        assert_eq!(cropped.get_pixel(1, 1), Rgba([0, 0, 255, 255]));
+    }
+}
@@ -1,5 +0,0 @@
-
-// the image backend trait
-//
-pub trait ImageBackend {
-}
@@ -0,0 +1,158 @@
+//! Backends for decoding, processing and encoding images.
+//!
+//! The backend is selected at compile time by cargo features, see
+//! [`Backend`]. The `image-crate` feature, which is enabled by default,
+//! selects [`ImageCrate`]. [`crate::image::Image`] holds the decoded pixels as
+//! the [`ImageBackend::Image`] of the selected backend and only processes them
+//! through the [`ImageBackend`] trait.
+
+#[cfg(feature = "image-crate")]
+mod image_crate;
+
+#[cfg(feature = "image-crate")]
+pub use image_crate::ImageCrate;
+
+#[cfg(not(feature = "image-crate"))]
+compile_error!("an image backend feature such as `image-crate` must be enabled");
+
+use super::adjustments::Adjustments;
+use super::animation::Frame;
+use super::bounds::{Rect, Size};
+use super::color::Color;
+use super::filters::Filters;
+use super::image::{Error, Format, Optimizations, ResizeFilter};
+use super::limits::Limits;
+use super::orientation::Orientation;
+use super::palette::Palette;
+use super::placeholder::{self, Placeholder};
+use super::watermark::Watermark;
+use std::io::{BufRead, Seek, Write};
+
+/// Backend selected by the enabled features.
+#[cfg(feature = "image-crate")]
+pub type Backend = ImageCrate;
+
+/// Decoded image of the selected backend.
+pub type BackendImage = <Backend as ImageBackend>::Image;
+
+/// Seekable source of an encoded image.
+pub trait Input: BufRead + Seek {}
+
+impl<T: BufRead + Seek> Input for T {}
+
+/// Seekable destination of an encoded image.
+pub trait Output: Write + Seek {}
+
+impl<T: Write + Seek> Output for T {}
+
+/// Layout of the pixels of a decoded image.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PixelFormat {
+    /// name of the color type, e.g. `rgba8`
+    pub color_type: String,
+    /// bits per channel
+    pub bit_depth: u16,
+    pub bytes_per_pixel: u16,
+    pub has_alpha: bool,
+    /// false for grayscale pixels
+    pub has_color: bool,
+}
+
+/// Image processing backend.
+pub trait ImageBackend: Send + Sync {
+    /// Decoded image owned by the backend.
+    type Image: Clone + Default + std::fmt::Debug + Send + Sync;
+
+    /// Reads the format and the dimensions from the header without decoding the pixels.
+    ///
+    /// The reader is rewound to its initial position afterwards.
+    fn dimensions(&self, reader: &mut dyn Input) -> Result<(Option<Format>, Size), Error>;
+
+    /// Decodes the first frame, allocating at most `max_alloc` bytes of the limits.
+    fn decode(&self, reader: &mut dyn Input, limits: &Limits) -> Result<Self::Image, Error>;
+
+    /// Decodes all frames of an animation within the allocation limit.
+    ///
+    /// Returns `None` for formats without animations. The reader is rewound
+    /// to its initial position afterwards.
+    fn decode_frames(
+        &self,
+        reader: &mut dyn Input,
+        format: Option<Format>,
+        limits: &Limits,
+    ) -> Result<Option<Vec<Frame<Self::Image>>>, Error>;
+
+    fn size(&self, image: &Self::Image) -> Size;
+
+    fn pixel_format(&self, image: &Self::Image) -> PixelFormat;
+
+    /// Resizes the image to exactly the size.
+    fn resize(&self, image: Self::Image, size: Size, filter: ResizeFilter) -> Self::Image;
+
+    /// Crops the image to the window, which is within the image.
+    fn crop(&self, image: Self::Image, window: Rect) -> Self::Image;
+
+    /// Window of the size on the most salient region of the image.
+    fn salient_window(&self, image: &Self::Image, size: Size) -> Rect;
+
+    /// Rotates and then flips the image.
+    fn orient(&self, image: Self::Image, orientation: Orientation) -> Self::Image;
+
+    /// Places the image at the window of a canvas filled with the background.
+    ///
+    /// Images without alpha stay opaque if the background is opaque.
+    fn pad(&self, image: Self::Image, canvas: Size, window: Rect, background: Color)
+        -> Self::Image;
+
+    /// Composites the image onto the background, removing the alpha channel.
+    fn flatten(&self, image: &Self::Image, background: Color) -> Self::Image;
+
+    fn adjust(&self, image: Self::Image, adjustments: &Adjustments) -> Self::Image;
+
+    fn filter(&self, image: Self::Image, filters: &Filters) -> Self::Image;
+
+    fn overlay(&self, image: Self::Image, watermark: &Watermark) -> Self::Image;
+
+    fn palette(&self, image: &Self::Image, colors: usize) -> Palette;
+
+    fn placeholder(&self, image: &Self::Image) -> Result<Placeholder, placeholder::Error>;
+
+    /// Structural similarity between 0 and 1 of the encoded image to the reference.
+    fn similarity(
+        &self,
+        reference: &Self::Image,
+        encoded: &[u8],
+        format: Format,
+    ) -> Result<f64, Error>;
+
+    /// Encodes the image in the format.
+    ///
+    /// Quality settings of the optimizations are resolved before, hence
+    /// `quality=auto` and `max_bytes` are ignored.
+    fn encode(
+        &self,
+        image: &Self::Image,
+        w: &mut dyn Output,
+        format: Format,
+        optimizations: &Optimizations,
+    ) -> Result<(), Error>;
+
+    /// Encodes all frames of an animation in the format.
+    ///
+    /// Formats without animations keep the first frame only.
+    fn encode_frames(
+        &self,
+        frames: &[Frame<Self::Image>],
+        w: &mut dyn Output,
+        format: Format,
+        optimizations: &Optimizations,
+    ) -> Result<(), Error>;
+}
+
+/// Returns the backend selected by the enabled features.
+#[cfg(feature = "image-crate")]
+#[inline]
+#[must_use]
+pub fn default_backend() -> &'static Backend {
+    &ImageCrate
+}
@@ -1,15 +1,19 @@
+use super::params::{deserialize_from_str, Hundredths};
 use serde::Deserialize;
 
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
-    // #[error("image error: `{0}`")]
-    // Image(#[from] image::error::ImageError),
+    #[error("scaling {size} to a dimension of {dimension} overflows")]
+    Overflow { size: Size, dimension: u32 },
 
-    // #[error("io error: `{0}`")]
-    // Io(#[from] std::io::Error),
+    #[error("invalid bounds {bounds:?} for {size}")]
+    InvalidBounds { size: Size, bounds: Bounds },
+
+    #[error("invalid gravity `{0}`")]
+    InvalidGravity(String),
 }
 
-#[derive(Deserialize, Eq, PartialEq, Hash, Debug, Clone, Copy)]
+#[derive(Deserialize, Eq, PartialEq, Hash, Debug, Clone, Copy, Default)]
 pub enum ScalingMode {
     /// Fit into wxh if both are given.
     ///
@@ -20,6 +24,7 @@ pub enum ScalingMode {
     ///
     /// If at most one dimension is given, the larger image dimension is scaled to
     /// fit into ``min(w, h)``.
+    #[default]
     Fit,
 
     /// Fit to cover wxh while keeping aspect ratio.
@@ -27,14 +32,98 @@ pub enum ScalingMode {
     /// If at most one dimension is given, the smallest dimension is scaled up to
     /// cover ``min(w, h)``.
     Cover,
+
+    /// Fit to wxh while keeping aspect ratio and pad the remainder to wxh.
+    ///
+    /// If at most one dimension is given, this is equivalent to `Fit`.
+    Pad,
+}
+
+/// Focal point in relative image coordinates.
+///
+/// Both coordinates are in ``0..=1``, where ``(0, 0)`` is the top left corner.
+#[derive(Eq, PartialEq, Hash, Debug, Clone, Copy)]
+pub struct FocalPoint {
+    pub x: Hundredths,
+    pub y: Hundredths,
+}
+
+/// Anchor of the crop window when the scaled image overflows the bounds.
+#[derive(Eq, PartialEq, Hash, Debug, Clone, Copy, Default)]
+pub enum Gravity {
+    #[default]
+    Center,
+    North,
+    NorthEast,
+    East,
+    SouthEast,
+    South,
+    SouthWest,
+    West,
+    NorthWest,
+    /// Center the crop window on a focal point as close as possible.
+    Focal(FocalPoint),
+    /// Place the crop window on the most salient region of the image.
+    ///
+    /// Geometry alone cannot resolve this, [`Bounds::crop`] centers the window.
+    Smart,
 }
 
-impl Default for ScalingMode {
-    fn default() -> Self {
-        Self::Fit
+impl Gravity {
+    #[inline]
+    #[must_use]
+    pub fn focal_point(self) -> FocalPoint {
+        let (x, y) = match self {
+            Self::Center | Self::Smart => (50, 50),
+            Self::North => (50, 0),
+            Self::NorthEast => (100, 0),
+            Self::East => (100, 50),
+            Self::SouthEast => (100, 100),
+            Self::South => (50, 100),
+            Self::SouthWest => (0, 100),
+            Self::West => (0, 50),
+            Self::NorthWest => (0, 0),
+            Self::Focal(point) => return point,
+        };
+        FocalPoint {
+            x: Hundredths::new(x),
+            y: Hundredths::new(y),
+        }
     }
 }
 
+impl std::str::FromStr for Gravity {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || Error::InvalidGravity(s.to_string());
+        let gravity = match s.to_ascii_lowercase().replace(['-', '_'], "").as_str() {
+            "center" | "centre" => Self::Center,
+            "north" => Self::North,
+            "northeast" => Self::NorthEast,
+            "east" => Self::East,
+            "southeast" => Self::SouthEast,
+            "south" => Self::South,
+            "southwest" => Self::SouthWest,
+            "west" => Self::West,
+            "northwest" => Self::NorthWest,
+            "smart" => Self::Smart,
+            _ => {
+                let (x, y) = s.split_once(',').ok_or_else(invalid)?;
+                let coord =
+                    |c: &str| Hundredths::parse(c, "coordinate", 1.0).map_err(|_| invalid());
+                Self::Focal(FocalPoint {
+                    x: coord(x)?,
+                    y: coord(y)?,
+                })
+            }
+        };
+        Ok(gravity)
+    }
+}
+
+deserialize_from_str!(Gravity);
+
 #[derive(Deserialize, Debug, Clone, Copy)]
 pub struct Bounds {
     /// width of the image
@@ -43,9 +132,89 @@ pub struct Bounds {
     pub height: Option<u32>,
     /// mode of scaling
     pub mode: Option<ScalingMode>,
+    /// anchor for cropping the overflow in cover mode or placing the image in pad mode
+    pub gravity: Option<Gravity>,
+    /// allow scaling beyond the size of the source (default false), the pad
+    /// mode canvas always has the requested size
+    pub enlarge: Option<bool>,
 }
 
-#[derive(Debug, Clone, Copy)]
+impl Bounds {
+    /// Crop window that cuts a scaled image of the given size down to the bounds.
+    ///
+    /// Only images scaled in cover mode with both dimensions bounded overflow.
+    #[inline]
+    #[must_use]
+    pub fn crop(&self, size: Size) -> Option<Rect> {
+        let (width, height) = match *self {
+            Bounds {
+                width: Some(width),
+                height: Some(height),
+                mode: Some(ScalingMode::Cover),
+                ..
+            } => (width.min(size.width), height.min(size.height)),
+            _ => return None,
+        };
+        if (width, height) == (size.width, size.height) {
+            return None;
+        }
+        let focal = self.gravity.unwrap_or_default().focal_point();
+        // the window always fits, hence the cast cannot truncate
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let offset = |len: u32, window: u32, focal: f32| {
+            let center = f64::from(len) * f64::from(focal);
+            let start = (center - f64::from(window) / 2.0).round();
+            start.clamp(0.0, f64::from(len - window)) as u32
+        };
+        Some(Rect {
+            x: offset(size.width, width, focal.x.value()),
+            y: offset(size.height, height, focal.y.value()),
+            width,
+            height,
+        })
+    }
+
+    /// Canvas size and placement of a scaled image of the given size that
+    /// pads it to the bounds.
+    ///
+    /// Only images scaled in pad mode with both dimensions bounded are padded.
+    /// The canvas always covers the bounds, even if the image itself is not
+    /// enlarged.
+    #[inline]
+    #[must_use]
+    pub fn pad(&self, size: Size) -> Option<(Size, Rect)> {
+        let canvas = match *self {
+            Bounds {
+                width: Some(width),
+                height: Some(height),
+                mode: Some(ScalingMode::Pad),
+                ..
+            } => Size {
+                width: width.max(size.width),
+                height: height.max(size.height),
+            },
+            _ => return None,
+        };
+        if canvas == size {
+            return None;
+        }
+        let focal = self.gravity.unwrap_or_default().focal_point();
+        // the image always fits, hence the cast cannot truncate
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let offset = |len: u32, image: u32, focal: f32| {
+            (f64::from(len - image) * f64::from(focal)).round() as u32
+        };
+        let window = Rect {
+            x: offset(canvas.width, size.width, focal.x.value()),
+            y: offset(canvas.height, size.height, focal.y.value()),
+            width: size.width,
+            height: size.height,
+        };
+        Some((canvas, window))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Size {
     /// width
     pub width: u32,
@@ -53,15 +222,79 @@ pub struct Size {
     pub height: u32,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Rect {
+    /// horizontal offset from the left
+    pub x: u32,
+    /// vertical offset from the top
+    pub y: u32,
+    /// width
+    pub width: u32,
+    /// height
+    pub height: u32,
+}
+
+impl Rect {
+    #[inline]
+    #[must_use]
+    pub fn size(&self) -> Size {
+        Size {
+            width: self.width,
+            height: self.height,
+        }
+    }
+}
+
 impl std::fmt::Display for Size {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(f, "{}x{}", self.width, self.height)
     }
 }
 
+/// Computes `round(value * num / denom)`, never returning zero.
+#[inline]
+fn scale_dimension(value: u32, num: u32, denom: u32) -> Option<u32> {
+    let denom = u64::from(denom);
+    let scaled = (u64::from(value) * u64::from(num) + denom / 2) / denom;
+    u32::try_from(scaled.max(1)).ok()
+}
+
 impl Size {
+    #[inline]
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+
+    /// Scales to the bounds according to the scaling mode.
+    ///
+    /// Unless the bounds allow enlarging, sizes exceeding the source are
+    /// limited to the source size. In exact mode, each dimension is limited
+    /// on its own, otherwise the source size is returned.
     #[inline]
     pub fn fit_to_bounds(self, bounds: Bounds) -> Result<Self, Error> {
+        let scaled = self.scale_to_bounds(bounds)?;
+        if bounds.enlarge.unwrap_or(false) {
+            return Ok(scaled);
+        }
+        let size = match bounds.mode.unwrap_or_default() {
+            ScalingMode::Exact => Size {
+                width: scaled.width.min(self.width),
+                height: scaled.height.min(self.height),
+            },
+            _ if scaled.width > self.width || scaled.height > self.height => self,
+            _ => scaled,
+        };
+        Ok(size)
+    }
+
+    #[inline]
+    fn scale_to_bounds(self, bounds: Bounds) -> Result<Self, Error> {
+        if self.is_empty() || bounds.width == Some(0) || bounds.height == Some(0) {
+            return Err(Error::InvalidBounds { size: self, bounds });
+        }
+        let mode = bounds.mode.unwrap_or_default();
+        let landscape = self.width >= self.height;
         match bounds {
             // unbounded
             Bounds {
@@ -70,67 +303,336 @@ impl Size {
                 ..
             } => Ok(self),
             // single dimension is bounded
-            Bounds {
-                width: None,
-                height: Some(height),
-                mode,
-            } => self.fit(
-                Size {
-                    width: self.width,
-                    height,
-                },
-                mode,
-            ),
             Bounds {
                 width: Some(width),
                 height: None,
-                mode,
-            } => self.fit(
-                Size {
-                    width,
-                    height: self.height,
-                },
-                mode,
-            ),
+                ..
+            } => match mode {
+                ScalingMode::Exact => self.scale_to_width(width),
+                ScalingMode::Fit | ScalingMode::Pad if !landscape => self.scale_to_height(width),
+                ScalingMode::Cover if landscape => self.scale_to_height(width),
+                ScalingMode::Fit | ScalingMode::Pad | ScalingMode::Cover => {
+                    self.scale_to_width(width)
+                }
+            },
+            Bounds {
+                width: None,
+                height: Some(height),
+                ..
+            } => match mode {
+                ScalingMode::Exact => self.scale_to_height(height),
+                ScalingMode::Fit | ScalingMode::Pad if landscape => self.scale_to_width(height),
+                ScalingMode::Cover if !landscape => self.scale_to_width(height),
+                ScalingMode::Fit | ScalingMode::Pad | ScalingMode::Cover => {
+                    self.scale_to_height(height)
+                }
+            },
             // all dimensions bounded
             Bounds {
                 width: Some(width),
                 height: Some(height),
-                mode,
-            } => self.fit(Size { width, height }, mode),
+                ..
+            } => self.fit(Size { width, height }, Some(mode)),
+        }
+    }
+
+    /// Scales to the given width while keeping the aspect ratio.
+    #[inline]
+    pub fn scale_to_width(self, width: u32) -> Result<Self, Error> {
+        if self.is_empty() || width == 0 {
+            return Err(Error::InvalidBounds {
+                size: self,
+                bounds: Bounds {
+                    width: Some(width),
+                    height: None,
+                    mode: None,
+                    gravity: None,
+                    enlarge: None,
+                },
+            });
+        }
+        let height = scale_dimension(self.height, width, self.width).ok_or(Error::Overflow {
+            size: self,
+            dimension: width,
+        })?;
+        Ok(Size { width, height })
+    }
+
+    /// Scales to the given height while keeping the aspect ratio.
+    #[inline]
+    pub fn scale_to_height(self, height: u32) -> Result<Self, Error> {
+        if self.is_empty() || height == 0 {
+            return Err(Error::InvalidBounds {
+                size: self,
+                bounds: Bounds {
+                    height: Some(height),
+                    width: None,
+                    mode: None,
+                    gravity: None,
+                    enlarge: None,
+                },
+            });
         }
-        // size
-        // match size {
-        //     Ok(scaled) => Ok(scaled),
-        //     // Err(err) => Err(Error {
-        //     //     // size: self,
-        //     //     // bounds,
-        //     //     // mode,
-        //     //     // source: err.into(),
-        //     // }),
-        // }
+        let width = scale_dimension(self.width, height, self.height).ok_or(Error::Overflow {
+            size: self,
+            dimension: height,
+        })?;
+        Ok(Size { width, height })
     }
 
     #[inline]
     pub fn fit(self, size: Size, mode: Option<ScalingMode>) -> Result<Self, Error> {
-        // let target = size.into();
-        let mode = mode.unwrap_or_default();
-        if mode == ScalingMode::Exact {
-            return Ok(size);
+        if self.is_empty() || size.is_empty() {
+            return Err(Error::InvalidBounds {
+                size: self,
+                bounds: size.into(),
+            });
+        }
+        // compare the scale factors `size.width / self.width` and
+        // `size.height / self.height` without loss of precision
+        let width_scale = u64::from(size.width) * u64::from(self.height);
+        let height_scale = u64::from(size.height) * u64::from(self.width);
+        match mode.unwrap_or_default() {
+            ScalingMode::Exact => Ok(size),
+            ScalingMode::Fit | ScalingMode::Pad if width_scale <= height_scale => {
+                self.scale_to_width(size.width)
+            }
+            ScalingMode::Cover if width_scale >= height_scale => self.scale_to_width(size.width),
+            ScalingMode::Fit | ScalingMode::Pad | ScalingMode::Cover => {
+                self.scale_to_height(size.height)
+            }
         }
-        Ok(self)
-        // match (|| {
-        //     let scale = self.scale_factor(target, mode)?;
-        //     let scaled = self.scale_by::<_, Ceil>(scale.0)?;
-        //     Ok::<_, arithmetic::Error>(scaled)
-        // })() {
-        //     Ok(scaled_size) => Ok(scaled_size),
-        //     Err(err) => Err(ScaleToError {
-        //         size: self,
-        //         target,
-        //         mode,
-        //         source: err,
-        //     }),
-        // }
+    }
+}
+
+impl From<Size> for Bounds {
+    #[inline]
+    fn from(size: Size) -> Self {
+        Bounds {
+            width: Some(size.width),
+            height: Some(size.height),
+            mode: Some(ScalingMode::Exact),
+            gravity: None,
+            enlarge: Some(true),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Bounds, Error, FocalPoint, Gravity, Rect, ScalingMode, Size};
+    use crate::params::Hundredths;
+    use pretty_assertions::assert_eq;
+
+    fn size(width: u32, height: u32) -> Size {
+        Size { width, height }
+    }
+
+    fn bounds(width: Option<u32>, height: Option<u32>, mode: ScalingMode) -> Bounds {
+        Bounds {
+            width,
+            height,
+            mode: Some(mode),
+            gravity: None,
+            enlarge: Some(true),
+        }
+    }
+
+    #[test]
+    fn unbounded() {
+        let fitted = size(400, 300)
+            .fit_to_bounds(bounds(None, None, ScalingMode::Cover))
+            .unwrap();
+        assert_eq!(fitted, size(400, 300));
+    }
+
+    #[test]
+    fn exact() {
+        let src = size(400, 300);
+        let fit = |w, h| src.fit_to_bounds(bounds(w, h, ScalingMode::Exact)).unwrap();
+        assert_eq!(fit(Some(100), Some(100)), size(100, 100));
+        assert_eq!(fit(Some(200), None), size(200, 150));
+        assert_eq!(fit(None, Some(600)), size(800, 600));
+    }
+
+    #[test]
+    fn fit() {
+        let src = size(400, 300);
+        let fit = |w, h| src.fit_to_bounds(bounds(w, h, ScalingMode::Fit)).unwrap();
+        assert_eq!(fit(Some(200), Some(200)), size(200, 150));
+        assert_eq!(fit(Some(800), Some(300)), size(400, 300));
+        assert_eq!(fit(Some(100), None), size(100, 75));
+        assert_eq!(fit(None, Some(100)), size(100, 75));
+
+        let portrait = size(300, 400);
+        let fit = |w, h| {
+            portrait
+                .fit_to_bounds(bounds(w, h, ScalingMode::Fit))
+                .unwrap()
+        };
+        assert_eq!(fit(Some(100), None), size(75, 100));
+        assert_eq!(fit(Some(300), Some(200)), size(150, 200));
+    }
+
+    #[test]
+    fn cover() {
+        let src = size(400, 300);
+        let cover = |w, h| src.fit_to_bounds(bounds(w, h, ScalingMode::Cover)).unwrap();
+        assert_eq!(cover(Some(200), Some(200)), size(267, 200));
+        assert_eq!(cover(Some(100), Some(30)), size(100, 75));
+        assert_eq!(cover(Some(150), None), size(200, 150));
+        assert_eq!(cover(None, Some(150)), size(200, 150));
+
+        let portrait = size(300, 400);
+        let cover = |w, h| {
+            portrait
+                .fit_to_bounds(bounds(w, h, ScalingMode::Cover))
+                .unwrap()
+        };
+        assert_eq!(cover(None, Some(150)), size(150, 200));
+    }
+
+    #[test]
+    fn never_zero() {
+        let src = size(10_000, 10);
+        let fitted = src
+            .fit_to_bounds(bounds(Some(100), Some(100), ScalingMode::Fit))
+            .unwrap();
+        assert_eq!(fitted, size(100, 1));
+    }
+
+    #[test]
+    fn overflow() {
+        let src = size(1, u32::MAX);
+        let err = src
+            .fit_to_bounds(bounds(Some(2), None, ScalingMode::Exact))
+            .unwrap_err();
+        assert!(matches!(err, Error::Overflow { .. }));
+    }
+
+    #[test]
+    fn invalid_bounds() {
+        let err = size(400, 300)
+            .fit_to_bounds(bounds(Some(0), None, ScalingMode::Fit))
+            .unwrap_err();
+        assert!(matches!(err, Error::InvalidBounds { .. }));
+        let err = size(0, 300)
+            .fit(size(100, 100), Some(ScalingMode::Fit))
+            .unwrap_err();
+        assert!(matches!(err, Error::InvalidBounds { .. }));
+    }
+
+    #[test]
+    fn parse_gravity() {
+        assert_eq!("center".parse::<Gravity>().unwrap(), Gravity::Center);
+        assert_eq!("south-east".parse::<Gravity>().unwrap(), Gravity::SouthEast);
+        assert_eq!("NorthWest".parse::<Gravity>().unwrap(), Gravity::NorthWest);
+        assert_eq!(
+            "0.25,1".parse::<Gravity>().unwrap(),
+            Gravity::Focal(FocalPoint {
+                x: Hundredths::new(25),
+                y: Hundredths::new(100)
+            })
+        );
+        // negative zero is the same cache key as zero
+        assert_eq!(
+            "-0,0.5".parse::<Gravity>().unwrap(),
+            "0,0.5".parse::<Gravity>().unwrap()
+        );
+        assert_eq!("smart".parse::<Gravity>().unwrap(), Gravity::Smart);
+        assert!("1.5,0".parse::<Gravity>().is_err());
+        assert!("up".parse::<Gravity>().is_err());
+    }
+
+    #[test]
+    fn enlarge() {
+        let src = size(400, 300);
+        let fit = |w, h, mode| {
+            let bounds = Bounds {
+                enlarge: None,
+                ..bounds(w, h, mode)
+            };
+            src.fit_to_bounds(bounds).unwrap()
+        };
+        assert_eq!(fit(Some(800), None, ScalingMode::Fit), size(400, 300));
+        assert_eq!(fit(Some(200), None, ScalingMode::Fit), size(200, 150));
+        assert_eq!(
+            fit(Some(500), Some(500), ScalingMode::Cover),
+            size(400, 300)
+        );
+        assert_eq!(
+            fit(Some(300), Some(300), ScalingMode::Cover),
+            size(400, 300)
+        );
+        assert_eq!(
+            fit(Some(200), Some(200), ScalingMode::Cover),
+            size(267, 200)
+        );
+        assert_eq!(
+            fit(Some(800), Some(100), ScalingMode::Exact),
+            size(400, 100)
+        );
+
+        let enlarged = src
+            .fit_to_bounds(bounds(Some(800), None, ScalingMode::Fit))
+            .unwrap();
+        assert_eq!(enlarged, size(800, 600));
+    }
+
+    #[test]
+    fn crop() {
+        let cover = |gravity| Bounds {
+            gravity: Some(gravity),
+            ..bounds(Some(200), Some(200), ScalingMode::Cover)
+        };
+        let scaled = size(267, 200);
+        let window = |x, y| Rect {
+            x,
+            y,
+            width: 200,
+            height: 200,
+        };
+        assert_eq!(cover(Gravity::Center).crop(scaled), Some(window(34, 0)));
+        assert_eq!(cover(Gravity::West).crop(scaled), Some(window(0, 0)));
+        assert_eq!(cover(Gravity::SouthEast).crop(scaled), Some(window(67, 0)));
+        let focal = Gravity::Focal(FocalPoint {
+            x: Hundredths::new(60),
+            y: Hundredths::new(50),
+        });
+        assert_eq!(cover(focal).crop(scaled), Some(window(60, 0)));
+
+        assert_eq!(cover(Gravity::Center).crop(size(200, 200)), None);
+        let fit = bounds(Some(200), Some(200), ScalingMode::Fit);
+        assert_eq!(fit.crop(scaled), None);
+    }
+
+    #[test]
+    fn pad() {
+        let pad = |gravity| Bounds {
+            gravity: Some(gravity),
+            ..bounds(Some(200), Some(200), ScalingMode::Pad)
+        };
+        let scaled = size(400, 300).fit_to_bounds(pad(Gravity::Center)).unwrap();
+        assert_eq!(scaled, size(200, 150));
+        let placed = |x, y| {
+            Some((
+                size(200, 200),
+                Rect {
+                    x,
+                    y,
+                    width: 200,
+                    height: 150,
+                },
+            ))
+        };
+        assert_eq!(pad(Gravity::Center).pad(scaled), placed(0, 25));
+        assert_eq!(pad(Gravity::North).pad(scaled), placed(0, 0));
+        assert_eq!(pad(Gravity::South).pad(scaled), placed(0, 50));
+
+        assert_eq!(pad(Gravity::Center).pad(size(200, 200)), None);
+        let fit = bounds(Some(200), Some(200), ScalingMode::Fit);
+        assert_eq!(fit.pad(scaled), None);
+        let single = bounds(Some(200), None, ScalingMode::Pad);
+        assert_eq!(single.pad(scaled), None);
     }
 }
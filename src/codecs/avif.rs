@@ -0,0 +1,68 @@
+use image::{DynamicImage, ImageError, ImageFormat};
+use ravif::{Img, RGB8, RGBA8};
+use std::io::Write;
+
+pub const DEFAULT_QUALITY: u8 = 60; // 0-100
+pub const DEFAULT_SPEED: u8 = 6; // 1 (slowest) to 10 (fastest)
+
+/// Encodes the image as AVIF with the given quality and encoder speed.
+///
+/// Lower speeds compress better but take considerably longer.
+pub fn encode<W: Write>(
+    image: &DynamicImage,
+    w: &mut W,
+    quality: u8,
+    speed: u8,
+) -> Result<(), ImageError> {
+    let width = image.width() as usize;
+    let height = image.height() as usize;
+    let quality = f32::from(quality.min(100));
+    let encoder = ravif::Encoder::new()
+        .with_quality(quality)
+        .with_alpha_quality(quality)
+        .with_speed(speed.clamp(1, 10))
+        .with_num_threads(Some(1));
+    let encoded = if image.color().has_alpha() {
+        let pixels: Vec<RGBA8> = image
+            .to_rgba8()
+            .pixels()
+            .map(|p| RGBA8::new(p[0], p[1], p[2], p[3]))
+            .collect();
+        encoder.encode_rgba(Img::new(pixels.as_slice(), width, height))
+    } else {
+        let pixels: Vec<RGB8> = image
+            .to_rgb8()
+            .pixels()
+            .map(|p| RGB8::new(p[0], p[1], p[2]))
+            .collect();
+        encoder.encode_rgb(Img::new(pixels.as_slice(), width, height))
+    }
+    .map_err(|err| super::encoding_error(ImageFormat::Avif, err.to_string()))?;
+    w.write_all(&encoded.avif_file)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{DynamicImage, RgbImage, RgbaImage};
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn encode() {
+        let opaque = DynamicImage::ImageRgb8(RgbImage::from_fn(16, 8, |x, y| {
+            image::Rgb([
+                u8::try_from(x * 15).unwrap(),
+                u8::try_from(y * 30).unwrap(),
+                0,
+            ])
+        }));
+        let transparent =
+            DynamicImage::ImageRgba8(RgbaImage::from_pixel(16, 8, image::Rgba([0, 0, 255, 128])));
+        for image in [opaque, transparent] {
+            let mut encoded = Vec::new();
+            super::encode(&image, &mut encoded, 50, 10).unwrap();
+            // the `image` crate cannot sniff AVIF, check the file type box instead
+            assert_eq!(&encoded[4..12], b"ftypavif");
+        }
+    }
+}
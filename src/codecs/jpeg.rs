@@ -0,0 +1,239 @@
+use image::{DynamicImage, ImageError, ImageFormat};
+use serde::Deserialize;
+use std::io::Write;
+
+pub const DEFAULT_QUALITY: u8 = 70; // 1-100
+
+/// Chroma subsampling of JPEG images.
+#[derive(Deserialize, Eq, PartialEq, Hash, Debug, Clone, Copy, Default)]
+pub enum Subsampling {
+    /// full chroma resolution
+    #[serde(rename = "444")]
+    Chroma444,
+    /// half horizontal chroma resolution
+    #[serde(rename = "422")]
+    Chroma422,
+    /// half horizontal and vertical chroma resolution
+    #[default]
+    #[serde(rename = "420")]
+    Chroma420,
+}
+
+impl Subsampling {
+    /// Size of a chroma sample in luma pixels.
+    #[inline]
+    #[must_use]
+    pub fn pixel_size(self) -> (u8, u8) {
+        match self {
+            Self::Chroma444 => (1, 1),
+            Self::Chroma422 => (2, 1),
+            Self::Chroma420 => (2, 2),
+        }
+    }
+}
+
+/// Settings for encoding a JPEG image.
+#[derive(Eq, PartialEq, Hash, Debug, Clone, Copy)]
+pub struct Settings {
+    /// quality from 1 to 100
+    pub quality: u8,
+    pub progressive: bool,
+    pub subsampling: Subsampling,
+}
+
+impl Default for Settings {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            quality: DEFAULT_QUALITY,
+            progressive: false,
+            subsampling: Subsampling::default(),
+        }
+    }
+}
+
+/// Backend for encoding JPEG images.
+///
+/// Transparent images are encoded without their alpha channel.
+pub trait Encoder {
+    fn encode(
+        &self,
+        image: &DynamicImage,
+        w: &mut dyn Write,
+        settings: Settings,
+    ) -> Result<(), ImageError>;
+}
+
+/// Returns the encoder with the best compression among the enabled backends.
+#[inline]
+#[must_use]
+pub fn default_encoder() -> &'static dyn Encoder {
+    #[cfg(feature = "mozjpeg")]
+    return &MozJpeg;
+    #[cfg(not(feature = "mozjpeg"))]
+    return &JpegEncoder;
+}
+
+/// Pure Rust encoder based on the `jpeg-encoder` crate.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct JpegEncoder;
+
+impl Encoder for JpegEncoder {
+    fn encode(
+        &self,
+        image: &DynamicImage,
+        w: &mut dyn Write,
+        settings: Settings,
+    ) -> Result<(), ImageError> {
+        use jpeg_encoder::{ColorType, SamplingFactor};
+        let error = |msg: String| super::encoding_error(ImageFormat::Jpeg, msg);
+        let width = u16::try_from(image.width()).map_err(|_| error("width too large".into()))?;
+        let height = u16::try_from(image.height()).map_err(|_| error("height too large".into()))?;
+        let (data, color) = if image.color().has_color() {
+            (image.to_rgb8().into_raw(), ColorType::Rgb)
+        } else {
+            (image.to_luma8().into_raw(), ColorType::Luma)
+        };
+        let mut encoder = jpeg_encoder::Encoder::new(w, settings.quality.clamp(1, 100));
+        encoder.set_progressive(settings.progressive);
+        encoder.set_sampling_factor(match settings.subsampling {
+            Subsampling::Chroma444 => SamplingFactor::R_4_4_4,
+            Subsampling::Chroma422 => SamplingFactor::R_4_2_2,
+            Subsampling::Chroma420 => SamplingFactor::R_4_2_0,
+        });
+        encoder
+            .encode(&data, width, height, color)
+            .map_err(|err| error(err.to_string()))
+    }
+}
+
+/// Encoder based on mozjpeg, producing smaller files at equal quality.
+#[cfg(feature = "mozjpeg")]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct MozJpeg;
+
+#[cfg(feature = "mozjpeg")]
+impl Encoder for MozJpeg {
+    fn encode(
+        &self,
+        image: &DynamicImage,
+        w: &mut dyn Write,
+        settings: Settings,
+    ) -> Result<(), ImageError> {
+        use mozjpeg::{ColorSpace, Compress};
+        let (data, color_space) = if image.color().has_color() {
+            (image.to_rgb8().into_raw(), ColorSpace::JCS_RGB)
+        } else {
+            (image.to_luma8().into_raw(), ColorSpace::JCS_GRAYSCALE)
+        };
+        // libjpeg reports errors by unwinding
+        let encoded = std::panic::catch_unwind(|| {
+            let mut compress = Compress::new(color_space);
+            if settings.progressive {
+                compress.set_progressive_mode();
+            } else {
+                // a single scan keeps trellis quantization of the default profile
+                compress.set_optimize_scans(false);
+                compress.set_optimize_coding(true);
+            }
+            compress.set_size(image.width() as usize, image.height() as usize);
+            compress.set_quality(f32::from(settings.quality.clamp(1, 100)));
+            if color_space == ColorSpace::JCS_RGB {
+                let size = settings.subsampling.pixel_size();
+                compress.set_chroma_sampling_pixel_sizes(size, size);
+            }
+            let mut started = compress.start_compress(Vec::new())?;
+            started.write_scanlines(&data)?;
+            started.finish()
+        })
+        .map_err(|_| super::encoding_error(ImageFormat::Jpeg, "mozjpeg failed"))??;
+        w.write_all(&encoded)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Encoder, Settings, Subsampling};
+    use image::{DynamicImage, RgbImage};
+    use pretty_assertions::assert_eq;
+
+    /// Whether the frame is progressive and the sampling factors of its components.
+    fn frame_header(encoded: &[u8]) -> (bool, Vec<(u8, u8)>) {
+        let mut pos = 2;
+        while pos + 4 < encoded.len() {
+            let marker = encoded[pos + 1];
+            let len = usize::from(u16::from_be_bytes([encoded[pos + 2], encoded[pos + 3]]));
+            if marker == 0xC0 || marker == 0xC1 || marker == 0xC2 {
+                let segment = &encoded[pos + 4..pos + 2 + len];
+                let components = segment[6..]
+                    .chunks(3)
+                    .map(|c| (c[1] >> 4, c[1] & 0x0F))
+                    .collect();
+                return (marker == 0xC2, components);
+            }
+            pos += 2 + len;
+        }
+        panic!("missing frame header");
+    }
+
+    fn encoders() -> Vec<Box<dyn Encoder>> {
+        vec![
+            Box::new(super::JpegEncoder),
+            #[cfg(feature = "mozjpeg")]
+            Box::new(super::MozJpeg),
+        ]
+    }
+
+    #[test]
+    fn settings() {
+        let image = DynamicImage::ImageRgb8(RgbImage::from_fn(32, 16, |x, y| {
+            image::Rgb([
+                u8::try_from(x * 8).unwrap(),
+                u8::try_from(y * 16).unwrap(),
+                0,
+            ])
+        }));
+        for encoder in encoders() {
+            for (subsampling, luma) in [
+                (Subsampling::Chroma444, (1, 1)),
+                (Subsampling::Chroma422, (2, 1)),
+                (Subsampling::Chroma420, (2, 2)),
+            ] {
+                for progressive in [false, true] {
+                    let settings = Settings {
+                        quality: 80,
+                        progressive,
+                        subsampling,
+                    };
+                    let mut encoded = Vec::new();
+                    encoder.encode(&image, &mut encoded, settings).unwrap();
+                    assert_eq!(
+                        frame_header(&encoded),
+                        (progressive, vec![luma, (1, 1), (1, 1)])
+                    );
+                    let decoded = image::load_from_memory(&encoded).unwrap();
+                    assert_eq!((decoded.width(), decoded.height()), (32, 16));
+                }
+            }
+        }
+    }
+
+    #[cfg(feature = "mozjpeg")]
+    #[test]
+    fn mozjpeg_baseline_is_smaller() {
+        let image = DynamicImage::ImageRgb8(RgbImage::from_fn(64, 64, |x, y| {
+            let value = u8::try_from((x * x + y * 3) % 256).unwrap();
+            image::Rgb([value, 255 - value, u8::try_from(x * 4).unwrap()])
+        }));
+        let settings = Settings::default();
+        let encode = |encoder: &dyn Encoder| {
+            let mut encoded = Vec::new();
+            encoder.encode(&image, &mut encoded, settings).unwrap();
+            encoded
+        };
+        let mozjpeg = encode(&super::MozJpeg);
+        assert!(!frame_header(&mozjpeg).0);
+        assert!(mozjpeg.len() <= encode(&super::JpegEncoder).len());
+    }
+}
@@ -0,0 +1,18 @@
+//! Encoders for output formats and options not covered by the `image` crate.
+
+#[cfg(feature = "avif")]
+pub mod avif;
+pub mod jpeg;
+pub mod png;
+#[cfg(feature = "webp")]
+pub mod webp;
+
+#[inline]
+pub(crate) fn encoding_error(
+    format: image::ImageFormat,
+    msg: impl Into<String>,
+) -> image::ImageError {
+    use image::error::{EncodingError, ImageError, ImageFormatHint};
+    let msg: String = msg.into();
+    ImageError::Encoding(EncodingError::new(ImageFormatHint::Exact(format), msg))
+}
@@ -0,0 +1,331 @@
+use image::{DynamicImage, ImageError, ImageFormat};
+use std::collections::{hash_map::Entry, HashMap};
+use std::io::Write;
+
+const COMPRESSIONS: [png::Compression; 2] = [png::Compression::Default, png::Compression::Best];
+const FILTERS: [png::FilterType; 5] = [
+    png::FilterType::NoFilter,
+    png::FilterType::Sub,
+    png::FilterType::Up,
+    png::FilterType::Avg,
+    png::FilterType::Paeth,
+];
+
+/// Losslessly reduced representation of the pixels.
+#[derive(Debug)]
+struct Reduced {
+    color: png::ColorType,
+    depth: png::BitDepth,
+    data: Vec<u8>,
+    palette: Option<Vec<u8>>,
+    trns: Option<Vec<u8>>,
+}
+
+/// Encodes the image as PNG with the smallest losslessly reduced color type
+/// and bit depth, trying all filter strategies and compression levels.
+///
+/// Unused alpha channels are dropped, images with at most 256 colors are
+/// converted to a palette and grayscale images are packed to 1, 2 or 4 bits
+/// where possible.
+pub fn encode_optimized<W: Write>(image: &DynamicImage, w: &mut W) -> Result<(), ImageError> {
+    let reduced = reduce(image);
+    let mut best: Option<Vec<u8>> = None;
+    for compression in COMPRESSIONS {
+        let strategies = FILTERS
+            .iter()
+            .map(|&filter| (filter, png::AdaptiveFilterType::NonAdaptive))
+            .chain(std::iter::once((
+                png::FilterType::Sub,
+                png::AdaptiveFilterType::Adaptive,
+            )));
+        for (filter, adaptive) in strategies {
+            let mut encoded = Vec::new();
+            let mut encoder = png::Encoder::new(&mut encoded, image.width(), image.height());
+            encoder.set_color(reduced.color);
+            encoder.set_depth(reduced.depth);
+            if let Some(ref palette) = reduced.palette {
+                encoder.set_palette(palette.as_slice());
+            }
+            if let Some(ref trns) = reduced.trns {
+                encoder.set_trns(trns.as_slice());
+            }
+            encoder.set_compression(compression);
+            encoder.set_filter(filter);
+            encoder.set_adaptive_filter(adaptive);
+            let mut writer = encoder.write_header().map_err(encoding_error)?;
+            writer
+                .write_image_data(&reduced.data)
+                .map_err(encoding_error)?;
+            writer.finish().map_err(encoding_error)?;
+            if best.as_ref().is_none_or(|best| encoded.len() < best.len()) {
+                best = Some(encoded);
+            }
+        }
+    }
+    w.write_all(&best.unwrap_or_default())?;
+    Ok(())
+}
+
+#[inline]
+fn encoding_error(err: png::EncodingError) -> ImageError {
+    super::encoding_error(ImageFormat::Png, err.to_string())
+}
+
+fn reduce(image: &DynamicImage) -> Reduced {
+    let has_16_bits = image.color().bytes_per_pixel() > image.color().channel_count();
+    if has_16_bits {
+        let rgba = image.to_rgba16();
+        // 16 bit samples with equal high and low bytes are exact 8 bit values
+        if rgba.iter().any(|&v| v >> 8 != v & 0xff) {
+            return reduce_16_bit(&rgba);
+        }
+    }
+    let rgba = image.to_rgba8();
+    let opaque = rgba.pixels().all(|p| p[3] == u8::MAX);
+    let gray = rgba.pixels().all(|p| p[0] == p[1] && p[1] == p[2]);
+    if gray && opaque {
+        let lumas: Vec<u8> = rgba.pixels().map(|p| p[0]).collect();
+        return reduce_gray(&lumas, rgba.width());
+    }
+    if let Some(reduced) = reduce_palette(&rgba) {
+        return reduced;
+    }
+    let (color, channels) = match (gray, opaque) {
+        (true, false) => (png::ColorType::GrayscaleAlpha, [0, 3].as_slice()),
+        (false, true) => (png::ColorType::Rgb, [0, 1, 2].as_slice()),
+        _ => (png::ColorType::Rgba, [0, 1, 2, 3].as_slice()),
+    };
+    Reduced {
+        color,
+        depth: png::BitDepth::Eight,
+        data: rgba
+            .pixels()
+            .flat_map(|p| channels.iter().map(move |&c| p[c]))
+            .collect(),
+        palette: None,
+        trns: None,
+    }
+}
+
+fn reduce_16_bit(rgba: &image::ImageBuffer<image::Rgba<u16>, Vec<u16>>) -> Reduced {
+    let opaque = rgba.pixels().all(|p| p[3] == u16::MAX);
+    let gray = rgba.pixels().all(|p| p[0] == p[1] && p[1] == p[2]);
+    let (color, channels) = match (gray, opaque) {
+        (true, true) => (png::ColorType::Grayscale, [0].as_slice()),
+        (true, false) => (png::ColorType::GrayscaleAlpha, [0, 3].as_slice()),
+        (false, true) => (png::ColorType::Rgb, [0, 1, 2].as_slice()),
+        (false, false) => (png::ColorType::Rgba, [0, 1, 2, 3].as_slice()),
+    };
+    Reduced {
+        color,
+        depth: png::BitDepth::Sixteen,
+        data: rgba
+            .pixels()
+            .flat_map(|p| channels.iter().flat_map(move |&c| p[c].to_be_bytes()))
+            .collect(),
+        palette: None,
+        trns: None,
+    }
+}
+
+/// Packs grayscale values into the lowest bit depth that represents them exactly.
+fn reduce_gray(lumas: &[u8], width: u32) -> Reduced {
+    let depths = [
+        (png::BitDepth::One, 1),
+        (png::BitDepth::Two, 2),
+        (png::BitDepth::Four, 4),
+    ];
+    for (depth, bits) in depths {
+        let scale = u8::MAX / ((1 << bits) - 1);
+        if lumas.iter().all(|luma| luma % scale == 0) {
+            let values: Vec<u8> = lumas.iter().map(|luma| luma / scale).collect();
+            return Reduced {
+                color: png::ColorType::Grayscale,
+                depth,
+                data: pack(&values, width, bits),
+                palette: None,
+                trns: None,
+            };
+        }
+    }
+    Reduced {
+        color: png::ColorType::Grayscale,
+        depth: png::BitDepth::Eight,
+        data: lumas.to_vec(),
+        palette: None,
+        trns: None,
+    }
+}
+
+/// Converts images with at most 256 distinct colors to a palette.
+fn reduce_palette(rgba: &image::RgbaImage) -> Option<Reduced> {
+    let mut colors: Vec<[u8; 4]> = Vec::new();
+    let mut lookup: HashMap<[u8; 4], usize> = HashMap::new();
+    for pixel in rgba.pixels() {
+        let next = colors.len();
+        if let Entry::Vacant(entry) = lookup.entry(pixel.0) {
+            if next == 256 {
+                return None;
+            }
+            entry.insert(next);
+            colors.push(pixel.0);
+        }
+    }
+    // translucent entries first keep the transparency chunk short
+    colors.sort_by_key(|color| color[3] == u8::MAX);
+    for (index, color) in colors.iter().enumerate() {
+        lookup.insert(*color, index);
+    }
+    let (depth, bits) = match colors.len() {
+        0..=2 => (png::BitDepth::One, 1),
+        3..=4 => (png::BitDepth::Two, 2),
+        5..=16 => (png::BitDepth::Four, 4),
+        _ => (png::BitDepth::Eight, 8),
+    };
+    // palette indices are below 256
+    #[allow(clippy::cast_possible_truncation)]
+    let indices: Vec<u8> = rgba.pixels().map(|p| lookup[&p.0] as u8).collect();
+    let trns: Vec<u8> = colors
+        .iter()
+        .take_while(|color| color[3] != u8::MAX)
+        .map(|color| color[3])
+        .collect();
+    Some(Reduced {
+        color: png::ColorType::Indexed,
+        depth,
+        data: pack(&indices, rgba.width(), bits),
+        palette: Some(
+            colors
+                .iter()
+                .flat_map(|color| &color[..3])
+                .copied()
+                .collect(),
+        ),
+        trns: (!trns.is_empty()).then_some(trns),
+    })
+}
+
+/// Packs values of the given bit depth into rows, most significant bits first.
+fn pack(values: &[u8], width: u32, bits: u8) -> Vec<u8> {
+    if bits == 8 {
+        return values.to_vec();
+    }
+    let per_byte = usize::from(8 / bits);
+    let width = width as usize;
+    let mut data = Vec::with_capacity(values.len() / per_byte + values.len() / width.max(1) + 1);
+    for row in values.chunks(width.max(1)) {
+        for chunk in row.chunks(per_byte) {
+            let mut byte = 0_u8;
+            for (i, value) in chunk.iter().enumerate() {
+                // bit positions are below 8
+                #[allow(clippy::cast_possible_truncation)]
+                let shift = 8 - bits * (i as u8 + 1);
+                byte |= value << shift;
+            }
+            data.push(byte);
+        }
+    }
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{DynamicImage, GrayImage, ImageBuffer, Luma, Rgb, Rgba, RgbaImage};
+    use pretty_assertions::assert_eq;
+
+    fn optimize(image: &DynamicImage) -> (png::ColorType, png::BitDepth, Vec<u8>) {
+        let mut encoded = Vec::new();
+        super::encode_optimized(image, &mut encoded).unwrap();
+        let decoder = png::Decoder::new(encoded.as_slice());
+        let reader = decoder.read_info().unwrap();
+        let info = reader.info();
+        let (color, depth) = (info.color_type, info.bit_depth);
+        // decoding must yield the original pixels
+        let decoded = image::load_from_memory(&encoded).unwrap();
+        assert_eq!(decoded.to_rgba16(), image.to_rgba16());
+        (color, depth, encoded)
+    }
+
+    fn default_size(image: &DynamicImage) -> usize {
+        let mut encoded = std::io::Cursor::new(Vec::new());
+        image
+            .write_to(&mut encoded, image::ImageOutputFormat::Png)
+            .unwrap();
+        encoded.into_inner().len()
+    }
+
+    #[test]
+    fn palette() {
+        let colors = [[255, 0, 0], [0, 255, 0], [0, 0, 255]];
+        let image = DynamicImage::ImageRgba8(RgbaImage::from_fn(64, 64, |x, y| {
+            let [r, g, b] = colors[usize::try_from((x / 8 + y / 8) % 3).unwrap()];
+            Rgba([r, g, b, 255])
+        }));
+        let (color, depth, encoded) = optimize(&image);
+        assert_eq!(
+            (color, depth),
+            (png::ColorType::Indexed, png::BitDepth::Two)
+        );
+        assert!(encoded.len() < default_size(&image));
+    }
+
+    #[test]
+    fn transparent_palette() {
+        let image = DynamicImage::ImageRgba8(RgbaImage::from_fn(9, 3, |x, _| {
+            Rgba([0, 0, 255, if x % 2 == 0 { 255 } else { 0 }])
+        }));
+        let (color, depth, _) = optimize(&image);
+        assert_eq!(
+            (color, depth),
+            (png::ColorType::Indexed, png::BitDepth::One)
+        );
+    }
+
+    #[test]
+    fn bilevel() {
+        let image = DynamicImage::ImageLuma8(GrayImage::from_fn(13, 7, |x, y| {
+            Luma([if (x + y) % 3 == 0 { 255 } else { 0 }])
+        }));
+        let (color, depth, _) = optimize(&image);
+        assert_eq!(
+            (color, depth),
+            (png::ColorType::Grayscale, png::BitDepth::One)
+        );
+    }
+
+    #[test]
+    fn drops_unused_alpha() {
+        let image = DynamicImage::ImageRgba8(RgbaImage::from_fn(32, 32, |x, y| {
+            Rgba([
+                u8::try_from(x * 8).unwrap(),
+                u8::try_from(y * 8).unwrap(),
+                128,
+                255,
+            ])
+        }));
+        let (color, depth, _) = optimize(&image);
+        assert_eq!((color, depth), (png::ColorType::Rgb, png::BitDepth::Eight));
+    }
+
+    #[test]
+    fn keeps_16_bit() {
+        let image = DynamicImage::ImageRgb16(ImageBuffer::from_fn(4, 4, |x, y| {
+            let (x, y) = (u16::try_from(x).unwrap(), u16::try_from(y).unwrap());
+            Rgb([x * 1000 + 1, y * 1000, 7])
+        }));
+        let (color, depth, _) = optimize(&image);
+        assert_eq!(
+            (color, depth),
+            (png::ColorType::Rgb, png::BitDepth::Sixteen)
+        );
+
+        let exact = DynamicImage::ImageRgb16(ImageBuffer::from_fn(4, 4, |x, _| {
+            Rgb([u16::try_from(x).unwrap() * 257, 0, 0])
+        }));
+        let (color, depth, _) = optimize(&exact);
+        assert_eq!(
+            (color, depth),
+            (png::ColorType::Indexed, png::BitDepth::Two)
+        );
+    }
+}
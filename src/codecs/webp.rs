@@ -0,0 +1,322 @@
+use crate::animation::Frame;
+use image::{Delay, DynamicImage, ImageError, ImageFormat};
+use std::io::Write;
+
+pub const DEFAULT_QUALITY: u8 = 75; // 0-100
+
+/// Encodes the image as lossy WebP with the given quality, or losslessly.
+pub fn encode<W: Write>(
+    image: &DynamicImage,
+    w: &mut W,
+    quality: u8,
+    lossless: bool,
+) -> Result<(), ImageError> {
+    let (data, layout) = if image.color().has_alpha() {
+        (image.to_rgba8().into_raw(), webp::PixelLayout::Rgba)
+    } else {
+        (image.to_rgb8().into_raw(), webp::PixelLayout::Rgb)
+    };
+    let encoder = webp::Encoder::new(&data, layout, image.width(), image.height());
+    let encoded = encoder
+        .encode_simple(lossless, f32::from(quality.min(100)))
+        .map_err(|err| super::encoding_error(ImageFormat::WebP, format!("{:?}", err)))?;
+    w.write_all(&encoded)?;
+    Ok(())
+}
+
+/// Whether the extended header has the animation flag set.
+fn is_animated(data: &[u8]) -> bool {
+    data.len() >= 30 && &data[12..16] == b"VP8X" && data[20] & 0x02 != 0
+}
+
+/// Bytes allocated for the decoded frames of an animated WebP.
+///
+/// Read from the canvas size and the number of frame chunks of the RIFF
+/// container, without decoding. Still images allocate no frames.
+#[must_use]
+pub fn animation_alloc(data: &[u8]) -> u64 {
+    if !is_animated(data) {
+        return 0;
+    }
+    // canvas dimensions are stored minus one in 24 bits
+    let dimension =
+        |b: &[u8]| u64::from(b[0]) + (u64::from(b[1]) << 8) + (u64::from(b[2]) << 16) + 1;
+    let (width, height) = (dimension(&data[24..27]), dimension(&data[27..30]));
+    let mut frames = 0_u64;
+    let mut offset = 12_usize;
+    while let Some(header) = data.get(offset..offset.saturating_add(8)) {
+        if &header[..4] == b"ANMF" {
+            frames += 1;
+        }
+        let size = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+        // chunks are padded to an even size
+        let size = usize::try_from(size).unwrap_or(usize::MAX);
+        offset = offset
+            .saturating_add(8)
+            .saturating_add(size)
+            .saturating_add(size & 1);
+    }
+    frames.saturating_mul(width * height * 4)
+}
+
+/// Decodes all frames of an animated WebP.
+///
+/// Still images have no frames.
+pub fn decode_animation(data: &[u8]) -> Result<Vec<Frame>, ImageError> {
+    use image::error::{DecodingError, ImageFormatHint};
+    if !is_animated(data) {
+        return Ok(Vec::new());
+    }
+    let decoded = webp::AnimDecoder::new(data).decode().map_err(|err| {
+        ImageError::Decoding(DecodingError::new(
+            ImageFormatHint::Exact(ImageFormat::WebP),
+            err,
+        ))
+    })?;
+    let mut start = 0;
+    let mut frames = Vec::with_capacity(decoded.len());
+    for frame in &decoded {
+        let (width, height) = (frame.width(), frame.height());
+        let pixels = frame.get_image().to_vec();
+        let image = match frame.get_layout() {
+            webp::PixelLayout::Rgba => {
+                image::RgbaImage::from_raw(width, height, pixels).map(DynamicImage::ImageRgba8)
+            }
+            webp::PixelLayout::Rgb => {
+                image::RgbImage::from_raw(width, height, pixels).map(DynamicImage::ImageRgb8)
+            }
+        };
+        // timestamps mark the end of each frame
+        let end = frame.get_time_ms();
+        let delay = u32::try_from(end - start).unwrap_or_default();
+        start = end;
+        frames.push(Frame {
+            image: image.ok_or_else(|| {
+                ImageError::Decoding(DecodingError::new(
+                    ImageFormatHint::Exact(ImageFormat::WebP),
+                    "invalid frame size",
+                ))
+            })?,
+            delay: Delay::from_numer_denom_ms(delay, 1),
+        });
+    }
+    Ok(frames)
+}
+
+/// Encodes the frames as an infinitely looping animated WebP.
+///
+/// The animation encoder of the `webp` crate ends the animation at timestamp
+/// zero, which drops the delay of the last frame, hence libwebp is used directly.
+pub fn encode_animation<W: Write>(
+    frames: &[Frame],
+    w: &mut W,
+    quality: u8,
+    lossless: bool,
+) -> Result<(), ImageError> {
+    let error = |msg: String| super::encoding_error(ImageFormat::WebP, msg);
+    let (width, height) = match frames.first() {
+        Some(frame) => (frame.image.width(), frame.image.height()),
+        None => return Err(error("animation without frames".to_string())),
+    };
+    let mut config = webp::WebPConfig::new().map_err(|_| error("invalid config".to_string()))?;
+    config.lossless = i32::from(lossless);
+    config.alpha_compression = i32::from(!lossless);
+    config.quality = f32::from(quality.min(100));
+
+    let mut encoder =
+        AnimEncoder::new(width, height).ok_or_else(|| error("invalid canvas".to_string()))?;
+    let mut timestamp: i32 = 0;
+    for frame in frames {
+        let mut picture = Picture::rgba(&frame.image.to_rgba8())
+            .ok_or_else(|| error("invalid frame".to_string()))?;
+        encoder
+            .add(Some(&mut picture), timestamp, &config)
+            .map_err(error)?;
+        let (numer, denom) = frame.delay.numer_denom_ms();
+        let delay = i32::try_from(numer / denom.max(1)).unwrap_or(i32::MAX);
+        timestamp = timestamp.saturating_add(delay);
+    }
+    // the animation ends after the delay of the last frame
+    encoder.add(None, timestamp, &config).map_err(error)?;
+    encoder
+        .assemble(|encoded| w.write_all(encoded))
+        .map_err(error)??;
+    Ok(())
+}
+
+/// Owned libwebp animation encoder, looping infinitely.
+struct AnimEncoder(*mut libwebp_sys::WebPAnimEncoder);
+
+impl AnimEncoder {
+    fn new(width: u32, height: u32) -> Option<Self> {
+        use libwebp_sys::{
+            WebPAnimEncoderNewInternal, WebPAnimEncoderOptions, WebPAnimEncoderOptionsInitInternal,
+            WebPGetMuxABIVersion,
+        };
+        let (width, height) = (i32::try_from(width).ok()?, i32::try_from(height).ok()?);
+        let version = WebPGetMuxABIVersion();
+        let mut options = std::mem::MaybeUninit::<WebPAnimEncoderOptions>::uninit();
+        // SAFETY: the options are initialized before they are read, the
+        // default loop count of zero loops infinitely
+        let encoder = unsafe {
+            if WebPAnimEncoderOptionsInitInternal(options.as_mut_ptr(), version) == 0 {
+                return None;
+            }
+            WebPAnimEncoderNewInternal(width, height, options.as_ptr(), version)
+        };
+        (!encoder.is_null()).then_some(Self(encoder))
+    }
+
+    /// Adds a frame shown from the timestamp in milliseconds until the next
+    /// one, or ends the animation at the timestamp without a frame.
+    fn add(
+        &mut self,
+        frame: Option<&mut Picture>,
+        timestamp: i32,
+        config: &webp::WebPConfig,
+    ) -> Result<(), String> {
+        let (frame, config) = match frame {
+            Some(frame) => (std::ptr::addr_of_mut!(frame.0), std::ptr::addr_of!(*config)),
+            None => (std::ptr::null_mut(), std::ptr::null()),
+        };
+        // SAFETY: the encoder is valid and the frame and config outlive the call
+        let ok = unsafe { libwebp_sys::WebPAnimEncoderAdd(self.0, frame, timestamp, config) };
+        if ok == 0 {
+            return Err(self.error());
+        }
+        Ok(())
+    }
+
+    /// Passes the encoded animation to the closure.
+    fn assemble<T>(&mut self, f: impl FnOnce(&[u8]) -> T) -> Result<T, String> {
+        use libwebp_sys::{WebPAnimEncoderAssemble, WebPData, WebPDataClear};
+        let mut data = WebPData::default();
+        // SAFETY: the encoder is valid and the assembled data is owned by us
+        // until it is cleared
+        unsafe {
+            if WebPAnimEncoderAssemble(self.0, &mut data) == 0 {
+                return Err(self.error());
+            }
+            let result = f(std::slice::from_raw_parts(data.bytes, data.size));
+            WebPDataClear(&mut data);
+            Ok(result)
+        }
+    }
+
+    fn error(&self) -> String {
+        // SAFETY: the encoder is valid and the message is a C string owned by it
+        let msg = unsafe { libwebp_sys::WebPAnimEncoderGetError(self.0) };
+        if msg.is_null() {
+            return "unknown error".to_string();
+        }
+        // SAFETY: the message is not null
+        unsafe { std::ffi::CStr::from_ptr(msg) }
+            .to_string_lossy()
+            .into_owned()
+    }
+}
+
+impl Drop for AnimEncoder {
+    fn drop(&mut self) {
+        // SAFETY: the encoder is valid and not used afterwards
+        unsafe { libwebp_sys::WebPAnimEncoderDelete(self.0) }
+    }
+}
+
+/// Owned libwebp picture in ARGB.
+struct Picture(libwebp_sys::WebPPicture);
+
+impl Picture {
+    fn rgba(image: &image::RgbaImage) -> Option<Self> {
+        let mut picture = libwebp_sys::WebPPicture::new().ok()?;
+        picture.use_argb = 1;
+        picture.width = i32::try_from(image.width()).ok()?;
+        picture.height = i32::try_from(image.height()).ok()?;
+        let stride = picture.width.checked_mul(4)?;
+        // SAFETY: the buffer holds `height` rows of `stride` bytes, which are copied
+        let ok =
+            unsafe { libwebp_sys::WebPPictureImportRGBA(&mut picture, image.as_ptr(), stride) };
+        // the picture owns the imported pixels even if the import failed halfway
+        let picture = Self(picture);
+        (ok != 0).then_some(picture)
+    }
+}
+
+impl Drop for Picture {
+    fn drop(&mut self) {
+        // SAFETY: the picture was initialized and is not used afterwards
+        unsafe { libwebp_sys::WebPPictureFree(&mut self.0) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{Delay, DynamicImage, GenericImageView, RgbaImage};
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn encode() {
+        let image = DynamicImage::ImageRgba8(RgbaImage::from_fn(8, 4, |x, y| {
+            image::Rgba([
+                u8::try_from(x * 30).unwrap(),
+                u8::try_from(y * 60).unwrap(),
+                0,
+                255,
+            ])
+        }));
+        for lossless in [false, true] {
+            let mut encoded = Vec::new();
+            super::encode(&image, &mut encoded, 80, lossless).unwrap();
+            assert_eq!(
+                image::guess_format(&encoded).unwrap(),
+                image::ImageFormat::WebP
+            );
+            let decoded = image::load_from_memory(&encoded).unwrap();
+            assert_eq!(decoded.dimensions(), (8, 4));
+        }
+    }
+
+    #[test]
+    fn encode_animation() {
+        let frames = crate::animation::tests::frames(8, 4, &[[255, 0, 0, 255], [0, 0, 255, 255]]);
+        let mut encoded = std::io::Cursor::new(Vec::new());
+        super::encode_animation(&frames, &mut encoded, 80, false).unwrap();
+
+        encoded.set_position(0);
+        let decoded = crate::animation::decode(&mut encoded, Some(image::ImageFormat::WebP), None)
+            .unwrap()
+            .unwrap();
+        assert_eq!(decoded.len(), 2);
+        assert_eq!(decoded[0].image.dimensions(), (8, 4));
+        assert_eq!(decoded[0].delay, frames[0].delay);
+
+        // two RGBA frames of 8x4 pixels
+        assert_eq!(super::animation_alloc(encoded.get_ref()), 256);
+        let mut decode = |max_alloc| {
+            encoded.set_position(0);
+            crate::animation::decode(&mut encoded, Some(image::ImageFormat::WebP), max_alloc)
+        };
+        assert!(decode(Some(256)).is_ok());
+        assert!(matches!(
+            decode(Some(255)),
+            Err(image::ImageError::Limits(_))
+        ));
+    }
+
+    #[test]
+    fn animation_roundtrip() {
+        let colors = [[255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 255, 255]];
+        let mut frames = crate::animation::tests::frames(8, 4, &colors);
+        frames[2].delay = Delay::from_numer_denom_ms(300, 1);
+        let mut encoded = Vec::new();
+        super::encode_animation(&frames, &mut encoded, 80, true).unwrap();
+
+        let decoded = super::decode_animation(&encoded).unwrap();
+        let delays: Vec<_> = decoded.iter().map(|frame| frame.delay).collect();
+        // the last frame keeps its own delay
+        assert_eq!(
+            delays,
+            frames.iter().map(|frame| frame.delay).collect::<Vec<_>>()
+        );
+    }
+}
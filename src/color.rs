@@ -0,0 +1,174 @@
+use super::params::deserialize_from_str;
+use image::{DynamicImage, Rgb, RgbImage, Rgba};
+use serde::Serialize;
+
+/// RGBA color with 8 bit channels.
+///
+/// Parsed from hex notation with 3, 4, 6 or 8 digits and an optional `#`,
+/// from `rgb(r,g,b)` and `rgba(r,g,b,a)` with alpha from 0 to 1, or from
+/// `transparent`. Formatted in hex notation with a `#`, omitting the alpha of
+/// opaque colors.
+#[derive(Eq, PartialEq, Hash, Debug, Clone, Copy)]
+pub struct Color(pub [u8; 4]);
+
+impl Color {
+    pub const WHITE: Self = Self([255, 255, 255, 255]);
+    pub const TRANSPARENT: Self = Self([0, 0, 0, 0]);
+
+    #[inline]
+    #[must_use]
+    pub fn is_opaque(self) -> bool {
+        self.0[3] == u8::MAX
+    }
+
+    /// Composites the image onto this color, ignoring the alpha of the color.
+    #[must_use]
+    pub fn flatten(self, image: &DynamicImage) -> DynamicImage {
+        let rgba = image.to_rgba8();
+        let [r, g, b, _] = self.0;
+        let flattened = RgbImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+            let Rgba([pr, pg, pb, alpha]) = *rgba.get_pixel(x, y);
+            let blend = |fg: u8, bg: u8| {
+                let (fg, bg, alpha) = (u32::from(fg), u32::from(bg), u32::from(alpha));
+                // the weighted mean of two bytes is a byte
+                #[allow(clippy::cast_possible_truncation)]
+                let blended = ((fg * alpha + bg * (255 - alpha) + 127) / 255) as u8;
+                blended
+            };
+            Rgb([blend(pr, r), blend(pg, g), blend(pb, b)])
+        });
+        DynamicImage::ImageRgb8(flattened)
+    }
+}
+
+impl From<Color> for Rgba<u8> {
+    #[inline]
+    fn from(color: Color) -> Self {
+        Rgba(color.0)
+    }
+}
+
+impl std::fmt::Display for Color {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let [r, g, b, a] = self.0;
+        write!(f, "#{:02x}{:02x}{:02x}", r, g, b)?;
+        if !self.is_opaque() {
+            write!(f, "{:02x}", a)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::str::FromStr for Color {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid color `{}`", s);
+        let lower = s.trim().to_ascii_lowercase();
+        if lower == "transparent" {
+            return Ok(Self::TRANSPARENT);
+        }
+        if let Some(channels) = lower
+            .strip_prefix("rgba(")
+            .or_else(|| lower.strip_prefix("rgb("))
+            .and_then(|rest| rest.strip_suffix(')'))
+        {
+            let channels: Vec<&str> = channels.split(',').map(str::trim).collect();
+            let has_alpha = lower.starts_with("rgba(");
+            if channels.len() != if has_alpha { 4 } else { 3 } {
+                return Err(invalid());
+            }
+            let mut color = [u8::MAX; 4];
+            for (channel, value) in color.iter_mut().zip(&channels[..3]) {
+                *channel = value.parse().map_err(|_| invalid())?;
+            }
+            if has_alpha {
+                let alpha: f32 = channels[3].parse().map_err(|_| invalid())?;
+                if !(0.0..=1.0).contains(&alpha) {
+                    return Err(invalid());
+                }
+                // alpha is in range, hence the cast cannot truncate
+                #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+                let alpha = (alpha * 255.0).round() as u8;
+                color[3] = alpha;
+            }
+            return Ok(Self(color));
+        }
+        let hex = lower.strip_prefix('#').unwrap_or(&lower);
+        if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(invalid());
+        }
+        let digits: Vec<u8> = hex
+            .chars()
+            .filter_map(|c| c.to_digit(16).and_then(|d| u8::try_from(d).ok()))
+            .collect();
+        let color = match digits.len() {
+            // shorthand digits are repeated, e.g. `f` is `ff`
+            3 | 4 => {
+                let mut color = [u8::MAX; 4];
+                for (channel, digit) in color.iter_mut().zip(&digits) {
+                    *channel = digit * 17;
+                }
+                color
+            }
+            6 | 8 => {
+                let mut color = [u8::MAX; 4];
+                for (channel, pair) in color.iter_mut().zip(digits.chunks(2)) {
+                    *channel = pair[0] * 16 + pair[1];
+                }
+                color
+            }
+            _ => return Err(invalid()),
+        };
+        Ok(Self(color))
+    }
+}
+
+deserialize_from_str!(Color);
+
+impl Serialize for Color {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Color;
+    use image::{DynamicImage, Rgba, RgbaImage};
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn parse() {
+        let parse = |s: &str| s.parse::<Color>().map(|color| color.0);
+        assert_eq!(parse("#ff8000"), Ok([255, 128, 0, 255]));
+        assert_eq!(parse("FF800080"), Ok([255, 128, 0, 128]));
+        assert_eq!(parse("f80"), Ok([255, 136, 0, 255]));
+        assert_eq!(parse("#f808"), Ok([255, 136, 0, 136]));
+        assert_eq!(parse("rgb(1, 2, 3)"), Ok([1, 2, 3, 255]));
+        assert_eq!(parse("rgba(1,2,3,0.5)"), Ok([1, 2, 3, 128]));
+        assert_eq!(parse("transparent"), Ok([0, 0, 0, 0]));
+        assert!(parse("#ff80").is_ok());
+        assert!(parse("#ff800").is_err());
+        assert!(parse("rgba(1,2,3)").is_err());
+        assert!(parse("rgb(1,2,300)").is_err());
+        assert!(parse("red").is_err());
+
+        assert_eq!(Color([255, 128, 0, 255]).to_string(), "#ff8000");
+        assert_eq!(Color([255, 128, 0, 128]).to_string(), "#ff800080");
+    }
+
+    #[test]
+    fn flatten() {
+        let image = DynamicImage::ImageRgba8(RgbaImage::from_fn(3, 1, |x, _| match x {
+            0 => Rgba([0, 0, 0, 0]),
+            1 => Rgba([0, 0, 0, 255]),
+            _ => Rgba([255, 0, 0, 128]),
+        }));
+        let flattened = Color::WHITE.flatten(&image).to_rgb8().into_raw();
+        assert_eq!(flattened, vec![255, 255, 255, 0, 0, 0, 255, 127, 127]);
+    }
+}
@@ -0,0 +1,270 @@
+use crate::params::{deserialize_from_str, Hundredths};
+use image::{DynamicImage, ImageBuffer, Pixel, Primitive};
+
+/// Largest accepted blur radius, as the cost of blurring grows with it.
+const MAX_SIGMA: f32 = 50.0;
+/// Largest accepted sharpening amount.
+const MAX_AMOUNT: f32 = 10.0;
+/// Blur radius separating the edges enhanced by `sharpen`.
+const SHARPEN_SIGMA: f32 = 1.0;
+
+/// Gaussian blur with the given standard deviation in pixels.
+#[derive(Eq, PartialEq, Hash, Debug, Clone, Copy)]
+pub struct Blur {
+    pub sigma: Hundredths,
+}
+
+/// Sharpening of edges by the given amount, where 1 doubles the contrast of edges.
+#[derive(Eq, PartialEq, Hash, Debug, Clone, Copy)]
+pub struct Sharpen {
+    pub amount: Hundredths,
+}
+
+/// Unsharp mask with the given blur radius and the minimum brightness
+/// difference for a pixel to be sharpened.
+#[derive(Eq, PartialEq, Hash, Debug, Clone, Copy)]
+pub struct Unsharp {
+    pub sigma: Hundredths,
+    pub threshold: u8,
+}
+
+impl Blur {
+    #[inline]
+    #[must_use]
+    pub fn apply(self, image: &DynamicImage) -> DynamicImage {
+        image.blur(self.sigma.value())
+    }
+}
+
+impl Sharpen {
+    #[inline]
+    #[must_use]
+    pub fn apply(self, image: &DynamicImage) -> DynamicImage {
+        unsharpen(image, SHARPEN_SIGMA, self.amount.value(), 0)
+    }
+}
+
+impl Unsharp {
+    #[inline]
+    #[must_use]
+    pub fn apply(self, image: &DynamicImage) -> DynamicImage {
+        unsharpen(image, self.sigma.value(), 1.0, self.threshold)
+    }
+}
+
+impl std::str::FromStr for Blur {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let sigma = Hundredths::parse(s, "blur sigma", MAX_SIGMA)?;
+        Ok(Self { sigma })
+    }
+}
+
+impl std::str::FromStr for Sharpen {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let amount = Hundredths::parse(s, "sharpen amount", MAX_AMOUNT)?;
+        Ok(Self { amount })
+    }
+}
+
+impl std::str::FromStr for Unsharp {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (sigma, threshold) = match s.split_once(',') {
+            Some((sigma, threshold)) => (sigma, Some(threshold)),
+            None => (s, None),
+        };
+        let sigma = Hundredths::parse(sigma, "unsharp sigma", MAX_SIGMA)?;
+        let threshold = match threshold {
+            Some(threshold) => threshold.trim().parse().map_err(|_| {
+                format!(
+                    "invalid unsharp threshold `{}`, expected 0 to 255",
+                    threshold
+                )
+            })?,
+            None => 0,
+        };
+        Ok(Self { sigma, threshold })
+    }
+}
+
+/// Adds the difference to a blurred copy, scaled by `amount`, to every sample
+/// differing by more than `threshold` on a scale to 255.
+///
+/// Unlike `DynamicImage::unsharpen`, this darkens the dark side of edges too.
+fn unsharpen(image: &DynamicImage, sigma: f32, amount: f32, threshold: u8) -> DynamicImage {
+    fn apply<P, S>(
+        image: &ImageBuffer<P, Vec<S>>,
+        sigma: f32,
+        amount: f32,
+        threshold: u8,
+    ) -> ImageBuffer<P, Vec<S>>
+    where
+        P: Pixel<Subpixel = S> + 'static,
+        S: Primitive + 'static,
+    {
+        let max: f32 = num_traits::NumCast::from(S::DEFAULT_MAX_VALUE).unwrap_or(1.0);
+        let threshold = f32::from(threshold) / 255.0 * max;
+        let mut sharpened = image::imageops::blur(image, sigma);
+        for (sharpened, original) in sharpened.pixels_mut().zip(image.pixels()) {
+            *sharpened = original.map2(sharpened, |c, blurred| {
+                let c: f32 = num_traits::NumCast::from(c).unwrap_or_default();
+                let blurred: f32 = num_traits::NumCast::from(blurred).unwrap_or_default();
+                let diff = c - blurred;
+                let mut value = c;
+                if diff.abs() > threshold {
+                    value = (c + amount * diff).clamp(0.0, max);
+                }
+                // integer samples are rounded, float samples range from 0 to 1
+                if max > 1.0 {
+                    value = value.round();
+                }
+                num_traits::NumCast::from(value).unwrap_or(S::DEFAULT_MIN_VALUE)
+            });
+        }
+        sharpened
+    }
+
+    match image {
+        DynamicImage::ImageLuma8(image) => {
+            DynamicImage::ImageLuma8(apply(image, sigma, amount, threshold))
+        }
+        DynamicImage::ImageLumaA8(image) => {
+            DynamicImage::ImageLumaA8(apply(image, sigma, amount, threshold))
+        }
+        DynamicImage::ImageRgb8(image) => {
+            DynamicImage::ImageRgb8(apply(image, sigma, amount, threshold))
+        }
+        DynamicImage::ImageRgba8(image) => {
+            DynamicImage::ImageRgba8(apply(image, sigma, amount, threshold))
+        }
+        DynamicImage::ImageLuma16(image) => {
+            DynamicImage::ImageLuma16(apply(image, sigma, amount, threshold))
+        }
+        DynamicImage::ImageLumaA16(image) => {
+            DynamicImage::ImageLumaA16(apply(image, sigma, amount, threshold))
+        }
+        DynamicImage::ImageRgb16(image) => {
+            DynamicImage::ImageRgb16(apply(image, sigma, amount, threshold))
+        }
+        DynamicImage::ImageRgba16(image) => {
+            DynamicImage::ImageRgba16(apply(image, sigma, amount, threshold))
+        }
+        DynamicImage::ImageRgb32F(image) => {
+            DynamicImage::ImageRgb32F(apply(image, sigma, amount, threshold))
+        }
+        DynamicImage::ImageRgba32F(image) => {
+            DynamicImage::ImageRgba32F(apply(image, sigma, amount, threshold))
+        }
+        image => DynamicImage::ImageRgba16(apply(&image.to_rgba16(), sigma, amount, threshold)),
+    }
+}
+
+deserialize_from_str!(Blur, Sharpen, Unsharp);
+
+/// Filters applied after resizing, in the order blur, sharpen, unsharp mask.
+#[derive(Eq, PartialEq, Hash, Debug, Clone, Copy, Default)]
+pub struct Filters {
+    pub blur: Option<Blur>,
+    pub sharpen: Option<Sharpen>,
+    pub unsharp: Option<Unsharp>,
+}
+
+impl Filters {
+    #[inline]
+    #[must_use]
+    pub fn is_identity(&self) -> bool {
+        self.blur.is_none() && self.sharpen.is_none() && self.unsharp.is_none()
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn apply(&self, image: DynamicImage) -> DynamicImage {
+        let image = match self.blur {
+            Some(blur) => blur.apply(&image),
+            None => image,
+        };
+        let image = match self.sharpen {
+            Some(sharpen) => sharpen.apply(&image),
+            None => image,
+        };
+        match self.unsharp {
+            Some(unsharp) => unsharp.apply(&image),
+            None => image,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Blur, Filters, Sharpen, Unsharp};
+    use image::{DynamicImage, GrayImage, Luma};
+    use pretty_assertions::assert_eq;
+
+    /// dark left half and bright right half
+    fn edge() -> DynamicImage {
+        DynamicImage::ImageLuma8(GrayImage::from_fn(16, 4, |x, _| {
+            Luma([if x < 8 { 64 } else { 192 }])
+        }))
+    }
+
+    fn row(image: &DynamicImage) -> Vec<u8> {
+        image
+            .to_luma8()
+            .rows()
+            .next()
+            .unwrap()
+            .map(|p| p[0])
+            .collect()
+    }
+
+    #[test]
+    fn parse() {
+        let blur: Blur = "2.5".parse().unwrap();
+        assert_eq!(blur.sigma.to_string(), "2.50");
+        assert!("-1".parse::<Blur>().is_err());
+        assert!("100".parse::<Blur>().is_err());
+        assert!("11".parse::<Sharpen>().is_err());
+
+        let unsharp: Unsharp = "1.5,10".parse().unwrap();
+        assert_eq!(
+            (unsharp.sigma.to_string(), unsharp.threshold),
+            ("1.50".to_string(), 10)
+        );
+        assert_eq!("2".parse::<Unsharp>().unwrap().threshold, 0);
+        assert!("1,300".parse::<Unsharp>().is_err());
+    }
+
+    #[test]
+    fn blur_softens_edges() {
+        let filters = Filters {
+            blur: Some("2".parse().unwrap()),
+            ..Filters::default()
+        };
+        let blurred = row(&filters.apply(edge()));
+        assert!(blurred[7] > 64 && blurred[8] < 192);
+        assert_eq!(blurred[0], 64);
+    }
+
+    #[test]
+    fn sharpen_increases_contrast() {
+        for filters in [
+            Filters {
+                sharpen: Some("1".parse().unwrap()),
+                ..Filters::default()
+            },
+            Filters {
+                unsharp: Some("1,0".parse().unwrap()),
+                ..Filters::default()
+            },
+        ] {
+            let sharpened = row(&filters.apply(edge()));
+            assert!(sharpened[7] < 64 && sharpened[8] > 192);
+            assert_eq!((sharpened[0], sharpened[15]), (64, 192));
+        }
+    }
+}
@@ -0,0 +1,96 @@
+use super::quality_value::{QualityValue, TryFromValues};
+use crate::mime::Mime;
+use http_headers::{Header, HeaderName, HeaderValue};
+
+/// `Accept` header, defined in
+/// [RFC7231](https://tools.ietf.org/html/rfc7231#section-5.3.2)
+///
+/// The `Accept` header field can be used by user agents to specify
+/// response media types that are acceptable.
+///
+/// # ABNF
+///
+/// ```text
+/// Accept = #( media-range [ accept-params ] )
+/// ```
+///
+/// # Example Values
+///
+/// * `image/avif,image/webp,*/*`
+/// * `image/webp;q=0.9, image/*;q=0.8`
+///
+#[derive(Clone, Debug)]
+pub struct Accept(pub QualityValue);
+
+impl Header for Accept {
+    fn name() -> &'static HeaderName {
+        &http::header::ACCEPT
+    }
+
+    fn decode<'i, I>(values: &mut I) -> Result<Self, http_headers::Error>
+    where
+        I: Iterator<Item = &'i HeaderValue>,
+    {
+        Ok(Accept(QualityValue::try_from_values(values)?))
+    }
+
+    fn encode<E>(&self, values: &mut E)
+    where
+        E: Extend<HeaderValue>,
+    {
+        let value = HeaderValue::from(&self.0);
+        values.extend(std::iter::once(value));
+    }
+}
+
+impl Accept {
+    /// Returns whether the media type is explicitly accepted.
+    ///
+    /// Wildcard ranges such as `image/*` are not considered, as clients
+    /// commonly send them without supporting every image format.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use imop::headers::{Accept, HeaderValue};
+    ///
+    /// let accept = Accept(HeaderValue::from_static("image/webp, image/*;q=0.8").into());
+    ///
+    /// assert!(accept.accepts(&"image/webp".parse().unwrap()));
+    /// assert!(!accept.accepts(&"image/avif".parse().unwrap()));
+    /// ```
+    #[must_use]
+    pub fn accepts(&self, mime: &Mime) -> bool {
+        self.sorted_values().any(|value| {
+            let essence = value.split(';').next().unwrap_or_default().trim();
+            essence.eq_ignore_ascii_case(mime.essence_str())
+        })
+    }
+
+    /// Returns a quality sorted iterator of the acceptable media ranges
+    pub fn sorted_values(&self) -> impl Iterator<Item = &str> {
+        self.0.iter_acceptable()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Accept;
+    use crate::headers::HeaderValue;
+
+    #[test]
+    fn accepts() {
+        let val = HeaderValue::from_static("image/avif;q=0, IMAGE/WebP;q=0.9, image/*, */*;q=0.8");
+        let accept = Accept(val.into());
+
+        assert!(accept.accepts(&"image/webp".parse().unwrap()));
+        assert!(!accept.accepts(&"image/avif".parse().unwrap()));
+        assert!(!accept.accepts(&"image/png".parse().unwrap()));
+
+        let mut values = accept.sorted_values();
+        assert_eq!(values.next(), Some("image/*"));
+        assert_eq!(values.next(), Some("IMAGE/WebP"));
+        assert_eq!(values.next(), Some("*/*"));
+        assert_eq!(values.next(), None);
+    }
+}
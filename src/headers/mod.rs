@@ -1,7 +1,9 @@
+mod accept;
 mod accept_encoding;
 mod content_coding;
 mod quality_value;
 
+pub use accept::Accept;
 pub use accept_encoding::AcceptEncoding;
 pub use content_coding::ContentCoding;
 pub use http_headers::*;
@@ -267,6 +267,19 @@ mod sealed {
             values.sort();
             values.into_iter().map(|pair| pair.data)
         }
+
+        /// Returns the quality sorted values, skipping values that are
+        /// rejected with a quality of zero or carry an invalid quality.
+        pub fn iter_acceptable(&self) -> impl Iterator<Item = &str> {
+            let mut values: Vec<_> = self
+                .csv
+                .iter()
+                .filter_map(|v| QualityMeta::<Delm>::try_from(v).ok())
+                .filter(|meta| meta.quality > 0)
+                .collect();
+            values.sort();
+            values.into_iter().map(|pair| pair.data)
+        }
     }
 
     impl<Delm: QualityDelimiter> From<FlatCsv> for QualityValue<Delm> {
@@ -378,6 +391,17 @@ mod tests {
         assert_eq!(values.next(), None);
     }
 
+    #[test]
+    fn acceptable_values() {
+        let val = HeaderValue::from_static("image/webp;q=0.5, image/avif;q=0, image/png;q=x, */*");
+        let qual = QualityValue::<SemiQ>::from(val);
+
+        let mut values = qual.iter_acceptable();
+        assert_eq!(values.next(), Some("*/*"));
+        assert_eq!(values.next(), Some("image/webp"));
+        assert_eq!(values.next(), None);
+    }
+
     #[test]
     fn alternate_delimiter() {
         let val = HeaderValue::from_static("deflate, br;level=0.8, gzip;level=1");
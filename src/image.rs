@@ -1,5 +1,20 @@
-use super::bounds::{Bounds, ScalingMode, Size};
+use super::adjustments::{Adjustments, Factor, Gamma, Hue};
+use super::animation::Frame;
+use super::backends::{self, BackendImage, ImageBackend, PixelFormat};
+use super::bounds::{self, Bounds, Gravity, ScalingMode, Size};
+use super::codecs::jpeg::{self, Subsampling};
+use super::color::Color;
+use super::filters::{Blur, Filters, Sharpen, Unsharp};
+use super::headers::Accept;
+use super::limits::{Limit, LimitExceeded, Limits};
+use super::metadata::{self, ColorSpace, Metadata, MetadataPolicy};
 use super::mime::{self, Mime};
+use super::orientation::{Flip, Orientation, Rotation};
+use super::palette::Palette;
+use super::params::{deserialize_from_str, Hundredths};
+use super::placeholder::{self, Placeholder};
+use super::quality::{Quality, Ssim};
+use super::watermark::{Watermark, WatermarkId};
 pub use image::ImageFormat as Format;
 use serde::Deserialize;
 use std::borrow::Cow;
@@ -7,8 +22,6 @@ use std::io::Seek;
 use std::path::Path;
 use std::time::Instant;
 
-const DEFAULT_JPEG_QUALITY: u8 = 70; // 1-100
-
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
     #[error("image error: `{0}`")]
@@ -16,32 +29,311 @@ pub enum Error {
 
     #[error("io error: `{0}`")]
     Io(#[from] std::io::Error),
+
+    #[error("bounds error: `{0}`")]
+    Bounds(#[from] bounds::Error),
+
+    #[error("metadata error: `{0}`")]
+    Metadata(#[from] metadata::Error),
+
+    #[error("frame `{frame}` out of range for {count} frames")]
+    FrameOutOfRange { frame: usize, count: usize },
+
+    #[error("cannot encode within {max_bytes} bytes")]
+    ExceedsMaxBytes { max_bytes: usize },
+
+    #[error("limit error: `{0}`")]
+    LimitExceeded(#[from] LimitExceeded),
+}
+
+impl Error {
+    /// Reports allocation failures of a decoder as exceeding the allocation limit.
+    pub(crate) fn decoding(err: image::error::ImageError, limits: &Limits) -> Self {
+        match err {
+            image::error::ImageError::Limits(_) => Self::from(LimitExceeded {
+                limit: Limit::Allocation,
+                max: limits.max_alloc.unwrap_or_default(),
+            }),
+            err => Self::from(err),
+        }
+    }
+}
+
+/// Resampling filter used for resizing.
+#[derive(Deserialize, Eq, PartialEq, Hash, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum ResizeFilter {
+    Nearest,
+    Triangle,
+    CatmullRom,
+    Gaussian,
+    #[default]
+    Lanczos3,
+}
+
+impl From<ResizeFilter> for image::imageops::FilterType {
+    #[inline]
+    fn from(filter: ResizeFilter) -> Self {
+        match filter {
+            ResizeFilter::Nearest => Self::Nearest,
+            ResizeFilter::Triangle => Self::Triangle,
+            ResizeFilter::CatmullRom => Self::CatmullRom,
+            ResizeFilter::Gaussian => Self::Gaussian,
+            ResizeFilter::Lanczos3 => Self::Lanczos3,
+        }
+    }
+}
+
+impl std::str::FromStr for ResizeFilter {
+    type Err = serde::de::value::Error;
+
+    #[inline]
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        use serde::de::{value::StrDeserializer, IntoDeserializer};
+        let deserializer: StrDeserializer<'_, Self::Err> = s.into_deserializer();
+        Self::deserialize(deserializer)
+    }
+}
+
+/// Device pixel ratio in hundredths, between 1.0 and 4.0.
+#[derive(Eq, PartialEq, Ord, PartialOrd, Hash, Debug, Clone, Copy)]
+pub struct Dpr(Hundredths);
+
+impl Dpr {
+    pub const MIN: Self = Self(Hundredths::new(100));
+    pub const MAX: Self = Self(Hundredths::new(400));
+
+    /// Scales a dimension by the ratio, rounding to the nearest pixel.
+    #[inline]
+    #[must_use]
+    pub fn scale(self, value: u32) -> u32 {
+        let scaled = (u64::from(value) * u64::from(self.0.units()) + 50) / 100;
+        u32::try_from(scaled).unwrap_or(u32::MAX)
+    }
+}
+
+impl Default for Dpr {
+    #[inline]
+    fn default() -> Self {
+        Self::MIN
+    }
+}
+
+impl std::fmt::Display for Dpr {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl std::str::FromStr for Dpr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Hundredths::parse_in(s, "device pixel ratio", 1.0, 4.0).map(Self)
+    }
 }
 
+deserialize_from_str!(Dpr);
+
 #[derive(Deserialize, Eq, PartialEq, Hash, Debug, Clone, Copy)]
 pub struct Optimizations {
-    /// quality value for JPEG (0 to 100)
-    pub quality: Option<u8>,
+    /// quality value for lossy encodings (0 to 100), or `auto` for the lowest
+    /// quality that reaches the minimum similarity
+    pub quality: Option<Quality>,
+    /// minimum structural similarity to the image for `quality=auto` (0 to 1)
+    pub min_ssim: Option<Ssim>,
+    /// upper bound for the encoded size, lowering the quality of lossy encodings
+    pub max_bytes: Option<usize>,
+    /// use lossless compression if the format supports it
+    pub lossless: Option<bool>,
+    /// encoder speed for AVIF (1 slowest to 10 fastest)
+    pub speed: Option<u8>,
+    /// spend more time to losslessly shrink PNG output
+    pub optimize: Option<bool>,
+    /// use progressive JPEG encoding
+    pub progressive: Option<bool>,
+    /// chroma subsampling for JPEG
+    pub subsampling: Option<Subsampling>,
     /// width of the image
     pub width: Option<u32>,
     /// height of the image
     pub height: Option<u32>,
     /// mode of scaling
     pub mode: Option<ScalingMode>,
+    /// anchor for cropping to the bounds in cover mode or placing the image in pad mode
+    pub gravity: Option<Gravity>,
+    /// allow scaling beyond the size of the source (default false), the pad
+    /// mode canvas always has the requested size
+    pub enlarge: Option<bool>,
+    /// background color for padding and for flattening transparency
+    pub bg: Option<Color>,
+    /// clockwise rotation applied before resizing
+    pub rotate: Option<Rotation>,
+    /// mirroring applied before resizing
+    pub flip: Option<Flip>,
+    /// metadata of the source to keep in the output
+    pub metadata: Option<MetadataPolicy>,
+    /// resampling filter for resizing
+    pub filter: Option<ResizeFilter>,
+    /// device pixel ratio the width and height are multiplied with
+    pub dpr: Option<Dpr>,
+    /// zero based index of the frame to extract from an animation
+    pub frame: Option<usize>,
+    /// brightness factor, 1 keeps the brightness
+    pub brightness: Option<Factor>,
+    /// contrast factor, 1 keeps the contrast
+    pub contrast: Option<Factor>,
+    /// gamma correction, values above 1 brighten midtones
+    pub gamma: Option<Gamma>,
+    /// saturation factor, 0 removes all colors
+    pub saturation: Option<Factor>,
+    /// rotation of hues in degrees
+    pub hue: Option<Hue>,
+    /// convert to grayscale
+    pub grayscale: Option<bool>,
+    /// apply a sepia tone
+    pub sepia: Option<bool>,
+    /// invert the colors
+    pub invert: Option<bool>,
+    /// gaussian blur applied after resizing
+    pub blur: Option<Blur>,
+    /// sharpening applied after resizing
+    pub sharpen: Option<Sharpen>,
+    /// unsharp mask applied after resizing
+    pub unsharp: Option<Unsharp>,
+    /// respond with information about the source image instead of the image
+    pub info: Option<bool>,
+    /// id of a watermark configured on the server
+    pub watermark: Option<WatermarkId>,
     /// encoding format
     #[serde(default)]
-    #[serde(deserialize_with = "image_format_from_ext")]
-    pub format: Option<Format>,
+    #[serde(deserialize_with = "output_format_from_ext")]
+    pub format: Option<OutputFormat>,
 }
 
 impl Optimizations {
     #[must_use]
     #[inline]
     pub fn bounds(&self) -> Bounds {
+        let dpr = self.dpr.unwrap_or_default();
         Bounds {
-            width: self.width,
-            height: self.height,
+            width: self.width.map(|width| dpr.scale(width)),
+            height: self.height.map(|height| dpr.scale(height)),
             mode: self.mode,
+            gravity: self.gravity,
+            enlarge: self.enlarge,
+        }
+    }
+
+    /// Limits the device pixel ratio to a server side maximum.
+    #[must_use]
+    #[inline]
+    pub fn with_max_dpr(mut self, max: Dpr) -> Self {
+        self.dpr = self.dpr.map(|dpr| dpr.min(max));
+        self
+    }
+
+    /// Uses a server side default for enlarging if the request does not specify it.
+    #[must_use]
+    #[inline]
+    pub fn with_default_enlarge(mut self, enlarge: bool) -> Self {
+        self.enlarge = self.enlarge.or(Some(enlarge));
+        self
+    }
+
+    /// Uses a server side default similarity for `quality=auto` if the request does not specify it.
+    #[must_use]
+    #[inline]
+    pub fn with_default_min_ssim(mut self, min_ssim: Ssim) -> Self {
+        self.min_ssim = self.min_ssim.or(Some(min_ssim));
+        self
+    }
+
+    /// Exact quality value, unless unspecified or `auto`.
+    #[must_use]
+    #[inline]
+    pub fn quality(&self) -> Option<u8> {
+        match self.quality {
+            Some(Quality::Exact(quality)) => Some(quality),
+            _ => None,
+        }
+    }
+
+    /// Resolves `format=auto` to the best format accepted by the client.
+    ///
+    /// The resolved optimizations name the exact output format and hence can
+    /// be used as a cache key for the encoded image.
+    #[must_use]
+    #[inline]
+    pub fn negotiate_format(
+        mut self,
+        accept: Option<&Accept>,
+        source: Option<Format>,
+        has_alpha: bool,
+        is_animated: bool,
+    ) -> Self {
+        if self.format == Some(OutputFormat::Auto) {
+            let format = negotiate_format(accept, source, has_alpha, is_animated);
+            self.format = Some(OutputFormat::Exact(format));
+        }
+        self
+    }
+
+    /// Exact output format, if requested or negotiated.
+    #[must_use]
+    #[inline]
+    pub fn output_format(&self) -> Option<Format> {
+        match self.format {
+            Some(OutputFormat::Exact(format)) => Some(format),
+            _ => None,
+        }
+    }
+
+    /// Background color for padding and flattening transparency.
+    ///
+    /// Defaults to transparent if the format supports alpha and white
+    /// otherwise. Formats without alpha ignore the alpha of the color.
+    #[must_use]
+    #[inline]
+    pub fn background(&self, format: Format) -> Color {
+        match self.bg {
+            Some(bg) => bg,
+            None if supports_alpha(format) => Color::TRANSPARENT,
+            None => Color::WHITE,
+        }
+    }
+
+    #[must_use]
+    #[inline]
+    pub fn orientation(&self) -> Orientation {
+        Orientation {
+            rotate: self.rotate,
+            flip: self.flip,
+        }
+    }
+
+    #[must_use]
+    #[inline]
+    pub fn adjustments(&self) -> Adjustments {
+        Adjustments {
+            brightness: self.brightness,
+            contrast: self.contrast,
+            gamma: self.gamma,
+            saturation: self.saturation,
+            hue: self.hue,
+            grayscale: self.grayscale.unwrap_or(false),
+            sepia: self.sepia.unwrap_or(false),
+            invert: self.invert.unwrap_or(false),
+        }
+    }
+
+    #[must_use]
+    #[inline]
+    pub fn filters(&self) -> Filters {
+        Filters {
+            blur: self.blur,
+            sharpen: self.sharpen,
+            unsharp: self.unsharp,
         }
     }
 }
@@ -66,8 +358,98 @@ pub fn mime_of_format(format: Format) -> Option<Mime> {
     }
 }
 
+/// Lowest quality considered for `quality=auto`.
+const MIN_AUTO_QUALITY: u8 = 30;
+/// Highest quality considered for `quality=auto`, used if no lower quality
+/// reaches the minimum similarity.
+const MAX_AUTO_QUALITY: u8 = 95;
+
+/// Smallest dimension images are shrunk to for fitting a byte budget.
+const MIN_BUDGET_SIZE: u32 = 16;
+
+/// Size of the 32 bit float RGBA pixels color adjustments and filters work on.
+const FLOAT_BYTES_PER_PIXEL: u64 = 16;
+
+/// Highest quality to consider for a byte budget, if the encoding is lossy.
+fn max_quality(format: Format, optimizations: &Optimizations) -> Option<u8> {
+    let quality = optimizations.quality();
+    match format {
+        Format::Jpeg => Some(quality.unwrap_or(jpeg::DEFAULT_QUALITY)),
+        #[cfg(feature = "webp")]
+        Format::WebP if !optimizations.lossless.unwrap_or(false) => {
+            Some(quality.unwrap_or(crate::codecs::webp::DEFAULT_QUALITY))
+        }
+        #[cfg(feature = "avif")]
+        Format::Avif => Some(quality.unwrap_or(crate::codecs::avif::DEFAULT_QUALITY)),
+        _ => None,
+    }
+    .map(|quality| quality.min(100))
+}
+
+/// Whether images encoded in the format can be transparent.
+#[must_use]
+#[inline]
+pub fn supports_alpha(format: Format) -> bool {
+    matches!(
+        format,
+        Format::Png
+            | Format::Gif
+            | Format::WebP
+            | Format::Avif
+            | Format::Tiff
+            | Format::Tga
+            | Format::Ico
+            | Format::Bmp
+            | Format::OpenExr
+            | Format::Farbfeld
+    )
+}
+
+/// Picks the output format for `format=auto`.
+///
+/// AVIF is preferred over WebP if the client explicitly accepts it and the
+/// encoder is enabled. Otherwise web compatible source formats are kept and
+/// everything else is converted to JPEG, or PNG for transparent images.
+/// Animations are only encoded as WebP or GIF, which keep all frames.
+#[must_use]
+pub fn negotiate_format(
+    accept: Option<&Accept>,
+    source: Option<Format>,
+    has_alpha: bool,
+    is_animated: bool,
+) -> Format {
+    let accepts = |format| {
+        accept
+            .zip(mime_of_format(format))
+            .is_some_and(|(accept, mime)| accept.accepts(&mime))
+    };
+    if cfg!(feature = "avif") && !is_animated && accepts(Format::Avif) {
+        return Format::Avif;
+    }
+    if cfg!(feature = "webp") && accepts(Format::WebP) {
+        return Format::WebP;
+    }
+    if is_animated {
+        return Format::Gif;
+    }
+    match source {
+        Some(format @ (Format::Png | Format::Gif)) => format,
+        Some(Format::Jpeg) if !has_alpha => Format::Jpeg,
+        _ if has_alpha => Format::Png,
+        _ => Format::Jpeg,
+    }
+}
+
+/// Output format requested by the client.
+#[derive(Eq, PartialEq, Hash, Debug, Clone, Copy)]
+pub enum OutputFormat {
+    /// negotiate the format from the `Accept` header
+    Auto,
+    Exact(Format),
+}
+
 #[inline]
-fn image_format_from_ext<'de, D>(deserializer: D) -> Result<Option<Format>, D::Error>
+fn output_format_from_ext<'de, D>(deserializer: D) -> Result<Option<OutputFormat>, D::Error>
 where
     D: serde::Deserializer<'de>,
 {
@@ -75,6 +457,7 @@ where
     let s: Option<Cow<'de, str>> = Option::deserialize(deserializer)?;
     match s {
         None => Ok(None),
+        Some(s) if s.eq_ignore_ascii_case("auto") => Ok(Some(OutputFormat::Auto)),
         Some(s) => {
             let fmt = Format::from_extension(s.as_ref())
                 .ok_or_else(|| {
@@ -87,32 +470,21 @@ where
                 })
                 .map_err(error::ImageError::Unsupported)
                 .map_err(serde::de::Error::custom)?;
-            Ok(Some(fmt))
+            Ok(Some(OutputFormat::Exact(fmt)))
         }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Image {
-    inner: image::DynamicImage,
+    inner: BackendImage,
+    /// all frames of an animation, the first one being `inner`
+    frames: Vec<Frame<BackendImage>>,
     format: Option<Format>,
     size: Size,
-}
-
-impl std::ops::Deref for Image {
-    type Target = image::DynamicImage;
-
-    #[inline]
-    fn deref(&self) -> &Self::Target {
-        &self.inner
-    }
-}
-
-impl std::ops::DerefMut for Image {
-    #[inline]
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.inner
-    }
+    metadata: Metadata,
+    /// limits the image was decoded with, which also bound its processing
+    limits: Limits,
 }
 
 // #[derive(Debug)]
@@ -173,23 +545,164 @@ impl std::ops::DerefMut for Image {
 // }
 
 impl Image {
+    /// Decodes the image within the default limits.
     #[inline]
     pub fn new<R: std::io::BufRead + std::io::Seek>(reader: R) -> Result<Self, Error> {
-        use image::io::Reader as ImageReader;
+        Self::with_limits(reader, &Limits::default())
+    }
+
+    /// Decodes the image, checking the limits from the header before decoding the pixels.
+    ///
+    /// All metadata is read.
+    #[inline]
+    pub fn with_limits<R: std::io::BufRead + std::io::Seek>(
+        mut reader: R,
+        limits: &Limits,
+    ) -> Result<Self, Error> {
+        use std::io::SeekFrom;
+        let start = reader.stream_position()?;
+        let end = reader.seek(SeekFrom::End(0))?;
+        reader.seek(SeekFrom::Start(start))?;
+        limits.check_input(end - start)?;
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        Self::from_bytes(data, limits, MetadataPolicy::KeepAll)
+    }
+
+    /// Decodes the encoded image, checking the limits from the header before
+    /// decoding the pixels.
+    ///
+    /// Only metadata the policy may keep is read, so encoding with a policy
+    /// that keeps more embeds nothing beyond it.
+    #[inline]
+    pub fn from_bytes<B: Into<bytes::Bytes>>(
+        data: B,
+        limits: &Limits,
+        policy: MetadataPolicy,
+    ) -> Result<Self, Error> {
+        let data = data.into();
+        let backend = backends::default_backend();
         let now = Instant::now();
-        let reader = ImageReader::new(reader).with_guessed_format()?;
-        let format = reader.format();
-        let inner = reader.decode()?;
-        let size = Size {
-            width: inner.width(),
-            height: inner.height(),
+        limits.check_input(data.len() as u64)?;
+
+        let metadata = Metadata::read(&data, policy);
+        let mut reader = std::io::Cursor::new(data);
+        let orientation = Orientation::read(&mut reader)?;
+        let (format, header) = backend.dimensions(&mut reader)?;
+        limits.check_dimensions(header.width, header.height)?;
+
+        // only the allocation limit remains to be checked while decoding
+        let (inner, frames) = match backend.decode_frames(&mut reader, format, limits)? {
+            Some(mut frames) if frames.len() == 1 => (frames.remove(0).image, Vec::new()),
+            Some(frames) if !frames.is_empty() => (frames[0].image.clone(), frames),
+            _ => (backend.decode(&mut reader, limits)?, Vec::new()),
         };
+        let size = backend.size(&inner);
         crate::debug!("image decode took {:?}", now.elapsed());
-        Ok(Self {
+        let mut image = Self {
             inner,
+            frames,
             format,
             size,
-        })
+            metadata,
+            limits: *limits,
+        };
+        if let Some(orientation) = orientation {
+            image.orient(orientation);
+        }
+        Ok(image)
+    }
+
+    /// Rotates and flips the image, e.g. to display it upright.
+    #[inline]
+    pub fn orient(&mut self, orientation: Orientation) {
+        if orientation.is_identity() {
+            return;
+        }
+        let now = Instant::now();
+        let backend = backends::default_backend();
+        self.transform(|image| backend.orient(image, orientation));
+        crate::debug!("orienting took {:?}", now.elapsed());
+    }
+
+    /// Applies color adjustments, usually after resizing.
+    #[inline]
+    pub fn adjust(&mut self, adjustments: &Adjustments) -> Result<(), Error> {
+        if adjustments.is_identity() {
+            return Ok(());
+        }
+        self.check_size(self.size, FLOAT_BYTES_PER_PIXEL)?;
+        let now = Instant::now();
+        let backend = backends::default_backend();
+        self.transform(|image| backend.adjust(image, adjustments));
+        crate::debug!("adjusting colors took {:?}", now.elapsed());
+        Ok(())
+    }
+
+    /// Applies blur and sharpening filters, usually after resizing.
+    #[inline]
+    pub fn filter(&mut self, filters: &Filters) -> Result<(), Error> {
+        if filters.is_identity() {
+            return Ok(());
+        }
+        self.check_size(self.size, FLOAT_BYTES_PER_PIXEL)?;
+        let now = Instant::now();
+        let backend = backends::default_backend();
+        self.transform(|image| backend.filter(image, filters));
+        crate::debug!("filtering took {:?}", now.elapsed());
+        Ok(())
+    }
+
+    /// Composites the watermark onto the image, usually as the last step.
+    #[inline]
+    pub fn overlay(&mut self, watermark: &Watermark) {
+        let now = Instant::now();
+        let backend = backends::default_backend();
+        self.transform(|image| backend.overlay(image, watermark));
+        crate::debug!("watermarking took {:?}", now.elapsed());
+    }
+
+    /// Applies the transformation to the image and all frames of an animation.
+    fn transform<F>(&mut self, mut f: F)
+    where
+        F: FnMut(BackendImage) -> BackendImage,
+    {
+        if self.frames.is_empty() {
+            self.inner = f(std::mem::take(&mut self.inner));
+        } else {
+            for frame in &mut self.frames {
+                frame.image = f(std::mem::take(&mut frame.image));
+            }
+            self.inner = self.frames[0].image.clone();
+        }
+        self.size = backends::default_backend().size(&self.inner);
+    }
+
+    /// Number of frames, which is one for still images.
+    #[inline]
+    #[must_use]
+    pub fn frame_count(&self) -> usize {
+        self.frames.len().max(1)
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn is_animated(&self) -> bool {
+        !self.frames.is_empty()
+    }
+
+    /// Replaces an animation with one of its frames as a still image.
+    #[inline]
+    pub fn select_frame(&mut self, frame: usize) -> Result<(), Error> {
+        let count = self.frame_count();
+        if frame >= count {
+            return Err(Error::FrameOutOfRange { frame, count });
+        }
+        if self.is_animated() {
+            self.inner = self.frames.swap_remove(frame).image;
+            self.frames.clear();
+        }
+        Ok(())
     }
 
     // pub fn content_length(&self) -> usize {
@@ -214,15 +727,25 @@ impl Image {
     }
 
     #[inline]
-    pub fn resize(&mut self, bounds: Bounds) {
+    pub fn resize(&mut self, bounds: Bounds, filter: ResizeFilter) -> Result<(), Error> {
         let now = Instant::now();
-        let new_size = self.size.fit_to_bounds(bounds).unwrap();
-        self.inner = self.inner.resize_exact(
-            new_size.width,
-            new_size.height,
-            image::imageops::FilterType::Lanczos3,
-        );
-        crate::debug!("fitting to {} took {:?}", new_size, now.elapsed());
+        let new_size = self.size.fit_to_bounds(bounds)?;
+        let backend = backends::default_backend();
+        if new_size != self.size {
+            let bytes_per_pixel = self.pixel_format().bytes_per_pixel;
+            self.check_size(new_size, bytes_per_pixel.into())?;
+            self.transform(|image| backend.resize(image, new_size, filter));
+            crate::debug!("fitting to {} took {:?}", new_size, now.elapsed());
+        }
+        if let Some(mut window) = bounds.crop(self.size) {
+            if bounds.gravity == Some(Gravity::Smart) {
+                window = backend.salient_window(&self.inner, window.size());
+            }
+            // the window of the first frame is used for the whole animation
+            self.transform(|image| backend.crop(image, window));
+            crate::debug!("cropping to {} took {:?}", self.size, now.elapsed());
+        }
+        Ok(())
 
         // let (w, h) = self.size;
         // if let Some((w, h)) = fit_to_bounds(w, h, bounds) {
@@ -233,70 +756,268 @@ impl Image {
         // };
     }
 
+    /// Pads the image to the bounds in pad mode, filling the remainder with the background.
+    ///
+    /// Images without alpha stay opaque if the background is opaque.
+    #[inline]
+    pub fn pad(&mut self, bounds: Bounds, background: Color) -> Result<(), Error> {
+        if let Some((canvas, window)) = bounds.pad(self.size) {
+            // the canvas is allocated with 8 bit RGBA pixels
+            self.check_size(canvas, 4)?;
+            let now = Instant::now();
+            let backend = backends::default_backend();
+            self.transform(|image| backend.pad(image, canvas, window, background));
+            crate::debug!("padding to {} took {:?}", self.size, now.elapsed());
+        }
+        Ok(())
+    }
+
+    /// Checks that frames of the size stay within the limits of the image.
+    fn check_size(&self, size: Size, bytes_per_pixel: u64) -> Result<(), Error> {
+        self.limits.check_dimensions(size.width, size.height)?;
+        let bytes = u64::from(size.width) * u64::from(size.height) * bytes_per_pixel;
+        let frames = u64::try_from(self.frame_count()).unwrap_or(u64::MAX);
+        self.limits.check_alloc(bytes.saturating_mul(frames))?;
+        Ok(())
+    }
+
+    /// Dominant color and a palette of up to the given number of colors.
+    ///
+    /// Animations use their first frame.
+    #[inline]
+    #[must_use]
+    pub fn palette(&self, colors: usize) -> Palette {
+        let now = Instant::now();
+        let palette = backends::default_backend().palette(&self.inner, colors);
+        crate::debug!("extracting the palette took {:?}", now.elapsed());
+        palette
+    }
+
+    /// Blurred placeholder of the image.
+    ///
+    /// Animations use their first frame.
+    #[inline]
+    pub fn placeholder(&self) -> Result<Placeholder, placeholder::Error> {
+        backends::default_backend().placeholder(&self.inner)
+    }
+
+    /// Size of the image, or of each frame of an animation.
+    #[inline]
+    #[must_use]
+    pub fn size(&self) -> Size {
+        self.size
+    }
+
+    /// Layout of the decoded pixels.
+    #[inline]
+    #[must_use]
+    pub fn pixel_format(&self) -> PixelFormat {
+        backends::default_backend().pixel_format(&self.inner)
+    }
+
     #[inline]
     #[must_use]
     pub fn format(&self) -> Option<Format> {
         self.format
     }
 
+    #[inline]
+    #[must_use]
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    /// Encodes the image in the format.
+    ///
+    /// With a byte budget, lossy encodings use the highest quality up to the
+    /// requested one that fits, shrinking the image if even the lowest
+    /// quality exceeds the budget. `quality=auto` is resolved first and
+    /// bounds the quality for the budget.
+    ///
+    /// Returns the size of the encoded image, which is smaller than the image
+    /// if it was shrunk to fit the budget.
     #[inline]
     pub fn encode_to<W: std::io::Write + Seek>(
         &self,
         w: &mut W,
         format: Format,
-        quality: Option<u8>,
-    ) -> Result<(), Error> {
-        use image::{codecs, ImageEncoder, ImageOutputFormat};
+        optimizations: &Optimizations,
+    ) -> Result<Size, Error> {
+        let resolved;
+        let optimizations = if optimizations.quality == Some(Quality::Auto) {
+            resolved = Optimizations {
+                quality: self
+                    .auto_quality(format, optimizations)?
+                    .map(Quality::Exact),
+                ..*optimizations
+            };
+            &resolved
+        } else {
+            optimizations
+        };
+        match optimizations.max_bytes {
+            Some(max_bytes) => match max_quality(format, optimizations) {
+                Some(quality) => self.encode_within(w, format, optimizations, quality, max_bytes),
+                None => self
+                    .encode_exact(w, format, optimizations)
+                    .map(|_| self.size),
+            },
+            None => self
+                .encode_exact(w, format, optimizations)
+                .map(|_| self.size),
+        }
+    }
+
+    /// Lowest quality whose decoded output reaches the minimum similarity to the image.
+    ///
+    /// Returns `None` for lossless encodings, encodings that cannot be decoded
+    /// for comparison and animations, which use the default quality instead.
+    fn auto_quality(
+        &self,
+        format: Format,
+        optimizations: &Optimizations,
+    ) -> Result<Option<u8>, Error> {
+        let lossy = match format {
+            Format::Jpeg => true,
+            #[cfg(feature = "webp")]
+            Format::WebP => !optimizations.lossless.unwrap_or(false),
+            _ => false,
+        };
+        if !lossy || self.is_animated() {
+            return Ok(None);
+        }
         let now = Instant::now();
-        let data = self.inner.as_bytes();
-        let color = self.inner.color();
-        let width = self.inner.width();
-        let height = self.inner.height();
-        match format.into() {
-            ImageOutputFormat::Png => codecs::png::PngEncoder::new(w)
-                .write_image(data, width, height, color)
-                .map_err(Error::from),
-            ImageOutputFormat::Jpeg(_) => {
-                let quality = quality.unwrap_or(DEFAULT_JPEG_QUALITY);
-                codecs::jpeg::JpegEncoder::new_with_quality(w, quality)
-                    .write_image(data, width, height, color)
-                    .map_err(Error::from)
+        let min_ssim = optimizations.min_ssim.unwrap_or_default();
+        // JPEG output is compared against the flattened image it encodes
+        let backend = backends::default_backend();
+        let reference = if format == Format::Jpeg && self.pixel_format().has_alpha {
+            Cow::Owned(backend.flatten(&self.inner, optimizations.background(format)))
+        } else {
+            Cow::Borrowed(&self.inner)
+        };
+        // binary search for the lowest quality that is similar enough,
+        // assuming that the similarity grows with the quality
+        let (mut low, mut high) = (MIN_AUTO_QUALITY, MAX_AUTO_QUALITY);
+        while low < high {
+            let quality = low + (high - low) / 2;
+            let optimizations = Optimizations {
+                quality: Some(Quality::Exact(quality)),
+                ..*optimizations
+            };
+            let mut encoded = std::io::Cursor::new(Vec::new());
+            self.encode_pixels(&mut encoded, format, &optimizations)?;
+            if backend.similarity(&reference, encoded.get_ref(), format)? >= min_ssim.value() {
+                high = quality;
+            } else {
+                low = quality + 1;
             }
-            ImageOutputFormat::Gif => codecs::gif::GifEncoder::new(w)
-                .encode(data, width, height, color)
-                .map_err(Error::from),
-            ImageOutputFormat::Ico => codecs::ico::IcoEncoder::new(w)
-                .write_image(data, width, height, color)
-                .map_err(Error::from),
-            ImageOutputFormat::Bmp => codecs::bmp::BmpEncoder::new(w)
-                .write_image(data, width, height, color)
-                .map_err(Error::from),
-            ImageOutputFormat::Tiff => codecs::tiff::TiffEncoder::new(w)
-                .write_image(data, width, height, color)
-                .map_err(Error::from),
-            ImageOutputFormat::Unsupported(msg) => {
-                Err(Error::from(image::error::ImageError::Unsupported(
-                    image::error::UnsupportedError::from_format_and_kind(
-                        image::error::ImageFormatHint::Unknown,
-                        image::error::UnsupportedErrorKind::Format(
-                            image::error::ImageFormatHint::Name(msg),
-                        ),
-                    ),
-                )))
+        }
+        crate::debug!(
+            "picking quality {} for similarity {} took {:?}",
+            low,
+            min_ssim,
+            now.elapsed()
+        );
+        Ok(Some(low))
+    }
+
+    fn encode_within<W: std::io::Write>(
+        &self,
+        w: &mut W,
+        format: Format,
+        optimizations: &Optimizations,
+        max_quality: u8,
+        max_bytes: usize,
+    ) -> Result<Size, Error> {
+        let now = Instant::now();
+        let mut image = Cow::Borrowed(self);
+        loop {
+            // binary search for the highest quality within the budget
+            let (mut low, mut high) = (0, max_quality);
+            let mut best = None;
+            while low <= high {
+                let quality = low + (high - low) / 2;
+                let optimizations = Optimizations {
+                    quality: Some(Quality::Exact(quality)),
+                    ..*optimizations
+                };
+                let mut encoded = std::io::Cursor::new(Vec::new());
+                image.encode_exact(&mut encoded, format, &optimizations)?;
+                let encoded = encoded.into_inner();
+                if encoded.len() <= max_bytes {
+                    best = Some(encoded);
+                    low = quality + 1;
+                } else if let Some(lower) = quality.checked_sub(1) {
+                    high = lower;
+                } else {
+                    break;
+                }
+            }
+            if let Some(encoded) = best {
+                crate::debug!(
+                    "fitting {} into {} bytes took {:?}",
+                    image.size,
+                    max_bytes,
+                    now.elapsed()
+                );
+                w.write_all(&encoded)?;
+                return Ok(image.size);
+            }
+            let size = image.size;
+            if size.width <= MIN_BUDGET_SIZE || size.height <= MIN_BUDGET_SIZE {
+                return Err(Error::ExceedsMaxBytes { max_bytes });
             }
-            _ => Err(Error::from(image::error::ImageError::Unsupported(
-                image::error::UnsupportedError::from_format_and_kind(
-                    image::error::ImageFormatHint::Unknown,
-                    image::error::UnsupportedErrorKind::Format(
-                        image::error::ImageFormatHint::Name("missing format".to_string()),
-                    ),
-                ),
-            ))),
-        }?;
+            let smaller = Size {
+                width: (size.width * 3 / 4).max(1),
+                height: (size.height * 3 / 4).max(1),
+            };
+            image
+                .to_mut()
+                .resize(smaller.into(), ResizeFilter::default())?;
+        }
+    }
+
+    #[inline]
+    fn encode_exact<W: std::io::Write + Seek>(
+        &self,
+        w: &mut W,
+        format: Format,
+        optimizations: &Optimizations,
+    ) -> Result<(), Error> {
+        let now = Instant::now();
+        let policy = optimizations.metadata.unwrap_or_default();
+        // grayscale pixels are only kept by formats other than WebP
+        let color_space = if self.pixel_format().has_color || format == Format::WebP {
+            ColorSpace::Rgb
+        } else {
+            ColorSpace::Gray
+        };
+        let metadata = self.metadata.filter(policy)?.for_color_space(color_space);
+        if metadata.is_empty() || !matches!(format, Format::Png | Format::Jpeg | Format::WebP) {
+            self.encode_pixels(w, format, optimizations)?;
+        } else {
+            let mut buffer = std::io::Cursor::new(Vec::new());
+            self.encode_pixels(&mut buffer, format, optimizations)?;
+            metadata.embed(buffer.into_inner(), w)?;
+        }
         crate::debug!("encoding took {:?}", now.elapsed());
         Ok(())
     }
 
+    #[inline]
+    fn encode_pixels<W: std::io::Write + Seek>(
+        &self,
+        w: &mut W,
+        format: Format,
+        optimizations: &Optimizations,
+    ) -> Result<(), Error> {
+        let backend = backends::default_backend();
+        if self.is_animated() {
+            return backend.encode_frames(&self.frames, w, format, optimizations);
+        }
+        backend.encode(&self.inner, w, format, optimizations)
+    }
+
     // #[inline]
     // pub fn encode(&self, format: Format, quality: Option<u8>) -> Result<EncodedImage, Error> {
     //     let mut buffer = std::io::Cursor::new(Vec::new());
@@ -307,3 +1028,464 @@ impl Image {
     //     })
     // }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Dpr, Error, Format, Image, Optimizations, OutputFormat, ResizeFilter};
+    use crate::animation;
+    use crate::headers::{Accept, HeaderValue};
+    use pretty_assertions::assert_eq;
+
+    fn optimizations(query: &str) -> Optimizations {
+        serde_urlencoded::from_str(query).unwrap()
+    }
+
+    #[test]
+    fn parse_dpr() {
+        assert_eq!("2".parse::<Dpr>().unwrap().to_string(), "2.00");
+        assert_eq!("1.333".parse::<Dpr>().unwrap().to_string(), "1.33");
+        assert!("0.5".parse::<Dpr>().is_err());
+        assert!("4.5".parse::<Dpr>().is_err());
+    }
+
+    #[test]
+    fn dpr_scales_bounds() {
+        let bounds = optimizations("width=300&height=101&dpr=1.5").bounds();
+        assert_eq!((bounds.width, bounds.height), (Some(450), Some(152)));
+
+        let max = "2".parse().unwrap();
+        let clamped = optimizations("width=300&dpr=3").with_max_dpr(max);
+        assert_eq!(clamped.dpr, Some(max));
+        assert_eq!(clamped.bounds().width, Some(600));
+        assert_eq!(optimizations("width=300").bounds().width, Some(300));
+    }
+
+    #[test]
+    fn parse_filter() {
+        assert_eq!(
+            optimizations("filter=catmull-rom").filter,
+            Some(ResizeFilter::CatmullRom)
+        );
+        assert_eq!(
+            "nearest".parse::<ResizeFilter>().unwrap(),
+            ResizeFilter::Nearest
+        );
+        assert!("bicubic".parse::<ResizeFilter>().is_err());
+        assert!(serde_urlencoded::from_str::<Optimizations>("filter=Lanczos3").is_err());
+    }
+
+    #[test]
+    fn pre_shrink() {
+        let source = image::RgbImage::from_fn(200, 100, |x, y| {
+            image::Rgb([u8::try_from(x).unwrap(), u8::try_from(y).unwrap(), 0])
+        });
+        let mut encoded = std::io::Cursor::new(Vec::new());
+        image::DynamicImage::ImageRgb8(source)
+            .write_to(&mut encoded, image::ImageOutputFormat::Png)
+            .unwrap();
+        for filter in [ResizeFilter::Nearest, ResizeFilter::Lanczos3] {
+            encoded.set_position(0);
+            let mut img = Image::new(&mut encoded).unwrap();
+            // shrinking by more than the pre-shrink factor
+            img.resize(optimizations("width=30&height=15").bounds(), filter)
+                .unwrap();
+            assert_eq!((img.inner.width(), img.inner.height()), (30, 15));
+        }
+    }
+
+    #[test]
+    fn negotiate_format() {
+        let accept = |value| Accept(HeaderValue::from_static(value).into());
+        let negotiate = |accept: Option<&Accept>, source, has_alpha| {
+            optimizations("format=auto")
+                .negotiate_format(accept, Some(source), has_alpha, false)
+                .output_format()
+        };
+        let modern = accept("image/avif,image/webp,image/*,*/*;q=0.8");
+        let webp = accept("image/webp;q=0.9,image/avif;q=0");
+        let legacy = accept("image/*,*/*;q=0.8");
+
+        let best = if cfg!(feature = "avif") {
+            Format::Avif
+        } else if cfg!(feature = "webp") {
+            Format::WebP
+        } else {
+            Format::Jpeg
+        };
+        assert_eq!(negotiate(Some(&modern), Format::Jpeg, false), Some(best));
+        if cfg!(feature = "webp") {
+            assert_eq!(
+                negotiate(Some(&webp), Format::Jpeg, false),
+                Some(Format::WebP)
+            );
+        }
+        assert_eq!(
+            negotiate(Some(&legacy), Format::Jpeg, false),
+            Some(Format::Jpeg)
+        );
+        assert_eq!(negotiate(None, Format::Png, true), Some(Format::Png));
+        assert_eq!(negotiate(None, Format::Tiff, false), Some(Format::Jpeg));
+        assert_eq!(negotiate(None, Format::Tiff, true), Some(Format::Png));
+    }
+
+    #[test]
+    fn negotiate_animated_format() {
+        let img = animated_gif();
+        let accept = |value| Accept(HeaderValue::from_static(value).into());
+        let negotiate = |accept: &Accept| {
+            optimizations("format=auto")
+                .negotiate_format(
+                    Some(accept),
+                    img.format(),
+                    img.pixel_format().has_alpha,
+                    img.is_animated(),
+                )
+                .output_format()
+        };
+        // AVIF output would keep only the first frame
+        let modern = if cfg!(feature = "webp") {
+            Format::WebP
+        } else {
+            Format::Gif
+        };
+        assert_eq!(negotiate(&accept("image/avif,image/webp")), Some(modern));
+        assert_eq!(negotiate(&accept("image/avif")), Some(Format::Gif));
+    }
+
+    #[test]
+    fn parse_format() {
+        assert_eq!(
+            optimizations("format=auto").format,
+            Some(OutputFormat::Auto)
+        );
+        assert_eq!(
+            optimizations("format=png").format,
+            Some(OutputFormat::Exact(Format::Png))
+        );
+        let exact = optimizations("format=png").negotiate_format(None, None, false, false);
+        assert_eq!(exact.output_format(), Some(Format::Png));
+        assert!(serde_urlencoded::from_str::<Optimizations>("format=nope").is_err());
+    }
+
+    fn animated_gif() -> Image {
+        let colors = [[255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 255, 255]];
+        let frames = animation::tests::frames(40, 20, &colors);
+        let mut encoded = std::io::Cursor::new(Vec::new());
+        animation::encode_gif(&frames, &mut encoded).unwrap();
+        encoded.set_position(0);
+        Image::new(encoded).unwrap()
+    }
+
+    #[test]
+    fn limits() {
+        use crate::limits::{Limit, LimitExceeded, Limits};
+        let colors = [[255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 255, 255]];
+        let frames = animation::tests::frames(40, 20, &colors);
+        let mut gif = std::io::Cursor::new(Vec::new());
+        animation::encode_gif(&frames, &mut gif).unwrap();
+        let mut png = std::io::Cursor::new(Vec::new());
+        frames[0]
+            .image
+            .write_to(&mut png, image::ImageOutputFormat::Png)
+            .unwrap();
+        let decode = |encoded: &std::io::Cursor<Vec<u8>>, limits: Limits| match Image::with_limits(
+            std::io::Cursor::new(encoded.get_ref()),
+            &limits,
+        ) {
+            Ok(_) => None,
+            Err(Error::LimitExceeded(LimitExceeded { limit, max })) => Some((limit, max)),
+            Err(err) => panic!("unexpected error {}", err),
+        };
+
+        assert_eq!(decode(&png, Limits::default()), None);
+        let input = png.get_ref().len() as u64 - 1;
+        let cases = [
+            (
+                Limits {
+                    max_input_bytes: Some(input),
+                    ..Limits::NONE
+                },
+                Limit::InputBytes,
+                input,
+            ),
+            (
+                Limits {
+                    max_width: Some(39),
+                    ..Limits::NONE
+                },
+                Limit::Width,
+                39,
+            ),
+            (
+                Limits {
+                    max_height: Some(19),
+                    ..Limits::NONE
+                },
+                Limit::Height,
+                19,
+            ),
+            (
+                Limits {
+                    max_pixels: Some(799),
+                    ..Limits::NONE
+                },
+                Limit::Pixels,
+                799,
+            ),
+            (
+                Limits {
+                    max_alloc: Some(1000),
+                    ..Limits::NONE
+                },
+                Limit::Allocation,
+                1000,
+            ),
+        ];
+        for (limits, limit, max) in cases {
+            assert_eq!(decode(&png, limits), Some((limit, max)));
+        }
+
+        // each frame of the animation fits, but not all of them
+        let max_alloc = Limits {
+            max_alloc: Some(40 * 20 * 4 * 2),
+            ..Limits::NONE
+        };
+        assert_eq!(decode(&png, max_alloc), None);
+        assert_eq!(decode(&gif, max_alloc), Some((Limit::Allocation, 6400)));
+    }
+
+    #[test]
+    fn resize_animation() {
+        let mut img = animated_gif();
+        assert_eq!(img.frame_count(), 3);
+        img.resize(optimizations("width=10").bounds(), ResizeFilter::default())
+            .unwrap();
+
+        let mut encoded = std::io::Cursor::new(Vec::new());
+        img.encode_to(&mut encoded, Format::Gif, &optimizations(""))
+            .unwrap();
+        encoded.set_position(0);
+        let frames = animation::decode(&mut encoded, Some(Format::Gif), None)
+            .unwrap()
+            .unwrap();
+        assert_eq!(frames.len(), 3);
+        for frame in &frames {
+            assert_eq!(frame.image.width(), 10);
+            assert_eq!(frame.image.height(), 5);
+        }
+        assert_eq!(
+            frames[2].image.to_rgba8().get_pixel(5, 2).0,
+            [0, 0, 255, 255]
+        );
+    }
+
+    #[test]
+    fn pad_and_flatten() {
+        // transparent left half and opaque blue right half
+        let source = image::RgbaImage::from_fn(40, 20, |x, _| {
+            image::Rgba(if x < 20 { [0; 4] } else { [0, 0, 255, 255] })
+        });
+        let mut encoded = std::io::Cursor::new(Vec::new());
+        image::DynamicImage::ImageRgba8(source)
+            .write_to(&mut encoded, image::ImageOutputFormat::Png)
+            .unwrap();
+        encoded.set_position(0);
+        let mut img = Image::new(encoded).unwrap();
+
+        let optimizations = optimizations("width=20&height=20&mode=Pad&bg=ff0000&quality=95");
+        img.resize(optimizations.bounds(), ResizeFilter::default())
+            .unwrap();
+        img.pad(
+            optimizations.bounds(),
+            optimizations.background(Format::Jpeg),
+        )
+        .unwrap();
+        assert_eq!((img.inner.width(), img.inner.height()), (20, 20));
+
+        let mut encoded = std::io::Cursor::new(Vec::new());
+        img.encode_to(&mut encoded, Format::Jpeg, &optimizations)
+            .unwrap();
+        let decoded = image::load_from_memory(encoded.get_ref())
+            .unwrap()
+            .to_rgb8();
+        let close = |pixel: &image::Rgb<u8>, expected: [u8; 3]| {
+            pixel
+                .0
+                .iter()
+                .zip(expected)
+                .all(|(&a, b)| a.abs_diff(b) < 16)
+        };
+        // padding and transparent pixels are filled with the background
+        assert!(close(decoded.get_pixel(10, 1), [255, 0, 0]));
+        assert!(close(decoded.get_pixel(2, 10), [255, 0, 0]));
+        assert!(close(decoded.get_pixel(17, 10), [0, 0, 255]));
+    }
+
+    #[test]
+    fn adjustment_limits() {
+        use crate::limits::{Limit, LimitExceeded, Limits};
+        let mut encoded = std::io::Cursor::new(Vec::new());
+        image::DynamicImage::ImageRgb8(image::RgbImage::new(50, 50))
+            .write_to(&mut encoded, image::ImageOutputFormat::Png)
+            .unwrap();
+        // decoding fits, the float pixels of adjustments and filters do not
+        let limits = Limits {
+            max_alloc: Some(50 * 50 * 16 - 1),
+            ..Limits::NONE
+        };
+        let mut img = Image::with_limits(std::io::Cursor::new(encoded.get_ref()), &limits).unwrap();
+        let exceeded = |result| {
+            matches!(
+                result,
+                Err(Error::LimitExceeded(LimitExceeded {
+                    limit: Limit::Allocation,
+                    ..
+                }))
+            )
+        };
+        assert!(exceeded(
+            img.adjust(&optimizations("grayscale=true").adjustments())
+        ));
+        assert!(exceeded(img.filter(&optimizations("blur=1").filters())));
+        assert!(img.adjust(&optimizations("").adjustments()).is_ok());
+    }
+
+    #[test]
+    fn pad_limits() {
+        use crate::limits::{Limit, LimitExceeded, Limits};
+        let mut encoded = std::io::Cursor::new(Vec::new());
+        image::DynamicImage::ImageRgb8(image::RgbImage::new(1, 1))
+            .write_to(&mut encoded, image::ImageOutputFormat::Png)
+            .unwrap();
+        let pad = |query: &str, limits: Limits| {
+            let mut img =
+                Image::with_limits(std::io::Cursor::new(encoded.get_ref()), &limits).unwrap();
+            let optimizations = optimizations(query);
+            img.pad(
+                optimizations.bounds(),
+                optimizations.background(Format::Png),
+            )
+            .map(|_| img.size)
+        };
+
+        // the canvas is rejected before it is allocated
+        assert!(matches!(
+            pad("width=60000&height=60000&mode=Pad", Limits::default()),
+            Err(Error::LimitExceeded(LimitExceeded {
+                limit: Limit::Width,
+                max: 16_384
+            }))
+        ));
+        let limits = Limits {
+            max_alloc: Some(10_000),
+            ..Limits::NONE
+        };
+        assert!(matches!(
+            pad("width=60&height=60&mode=Pad", limits),
+            Err(Error::LimitExceeded(LimitExceeded {
+                limit: Limit::Allocation,
+                max: 10_000
+            }))
+        ));
+        assert_eq!(
+            pad("width=40&height=40&mode=Pad", limits).unwrap(),
+            crate::bounds::Size {
+                width: 40,
+                height: 40
+            }
+        );
+    }
+
+    #[test]
+    fn max_bytes() {
+        let mut seed = 1_u32;
+        let noise = image::RgbImage::from_fn(64, 64, |_, _| {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            let [r, g, b, _] = seed.to_be_bytes();
+            image::Rgb([r, g, b])
+        });
+        let mut encoded = std::io::Cursor::new(Vec::new());
+        image::DynamicImage::ImageRgb8(noise)
+            .write_to(&mut encoded, image::ImageOutputFormat::Png)
+            .unwrap();
+        encoded.set_position(0);
+        let img = Image::new(encoded).unwrap();
+        let encode = |query: &str| {
+            let mut encoded = std::io::Cursor::new(Vec::new());
+            img.encode_to(&mut encoded, Format::Jpeg, &optimizations(query))
+                .map(|size| (encoded.into_inner(), size))
+        };
+
+        let (unbounded, _) = encode("").unwrap();
+        let budget = unbounded.len() / 2;
+        let (bounded, size) = encode(&format!("max_bytes={}", budget)).unwrap();
+        assert!(bounded.len() <= budget);
+        let decoded = image::load_from_memory(&bounded).unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (64, 64));
+        assert_eq!((size.width, size.height), (64, 64));
+
+        // even the lowest quality exceeds the budget at full size
+        let budget = encode("quality=1").unwrap().0.len() - 1;
+        let (shrunk, size) = encode(&format!("max_bytes={}", budget)).unwrap();
+        assert!(shrunk.len() <= budget);
+        let decoded = image::load_from_memory(&shrunk).unwrap();
+        assert!(decoded.width() < 64);
+        assert_eq!(
+            (size.width, size.height),
+            (decoded.width(), decoded.height())
+        );
+        assert_eq!(img.size.width, 64);
+
+        // the lowest quality still searches before shrinking
+        let (lowest, size) = encode(&format!("quality=0&max_bytes={}", budget + 1)).unwrap();
+        assert!(lowest.len() <= budget + 1);
+        assert_eq!(size.width, 64);
+
+        assert!(matches!(
+            encode("max_bytes=10"),
+            Err(Error::ExceedsMaxBytes { max_bytes: 10 })
+        ));
+    }
+
+    #[test]
+    fn auto_quality() {
+        let gradient = image::RgbImage::from_fn(64, 64, |x, y| {
+            // coordinates are small
+            #[allow(clippy::cast_possible_truncation)]
+            let value = (x * 3 + y) as u8;
+            image::Rgb([value, 255 - value, 128])
+        });
+        let mut encoded = std::io::Cursor::new(Vec::new());
+        image::DynamicImage::ImageRgb8(gradient)
+            .write_to(&mut encoded, image::ImageOutputFormat::Png)
+            .unwrap();
+        encoded.set_position(0);
+        let img = Image::new(encoded).unwrap();
+        let encode = |query: &str| {
+            let mut encoded = std::io::Cursor::new(Vec::new());
+            img.encode_to(&mut encoded, Format::Jpeg, &optimizations(query))
+                .unwrap();
+            encoded.into_inner()
+        };
+
+        let auto = encode("quality=auto&min_ssim=0.98");
+        assert!(auto.len() < encode("quality=95").len());
+        let decoded = image::load_from_memory(&auto).unwrap();
+        assert!(crate::quality::ssim(&img.inner, &decoded) >= 0.98);
+        // the highest quality is used if no quality is similar enough
+        assert_eq!(encode("quality=auto&min_ssim=1"), encode("quality=95"));
+    }
+
+    #[test]
+    fn select_frame() {
+        let mut img = animated_gif();
+        img.select_frame(1).unwrap();
+        assert!(!img.is_animated());
+        assert_eq!(img.inner.to_rgba8().get_pixel(0, 0).0, [0, 255, 0, 255]);
+
+        assert!(matches!(
+            animated_gif().select_frame(3),
+            Err(Error::FrameOutOfRange { frame: 3, count: 3 })
+        ));
+    }
+}
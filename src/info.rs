@@ -0,0 +1,145 @@
+use super::image::Image;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// EXIF fields reported in the image information.
+///
+/// GPS positions are never reported.
+const EXIF_TAGS: [exif::Tag; 11] = [
+    exif::Tag::Make,
+    exif::Tag::Model,
+    exif::Tag::LensModel,
+    exif::Tag::Software,
+    exif::Tag::DateTimeOriginal,
+    exif::Tag::ExposureTime,
+    exif::Tag::FNumber,
+    exif::Tag::PhotographicSensitivity,
+    exif::Tag::FocalLength,
+    exif::Tag::Artist,
+    exif::Tag::Copyright,
+];
+
+/// Information about a source image.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Info {
+    /// width of the upright image
+    pub width: u32,
+    /// height of the upright image
+    pub height: u32,
+    /// file extension of the detected format, e.g. `png`
+    pub format: Option<String>,
+    /// color type of the decoded pixels, e.g. `rgba8`
+    pub color_type: String,
+    /// bits per channel
+    pub bit_depth: u16,
+    pub has_alpha: bool,
+    /// number of frames, 1 for still images
+    pub frame_count: usize,
+    /// size of the source file in bytes
+    pub file_size: u64,
+    /// selected EXIF fields by tag name
+    pub exif: BTreeMap<String, String>,
+}
+
+impl Info {
+    /// Describes the decoded image and the size of its source file.
+    ///
+    /// Invalid EXIF data is ignored.
+    #[must_use]
+    pub fn new(image: &Image, file_size: u64) -> Self {
+        let size = image.size();
+        let pixels = image.pixel_format();
+        Self {
+            width: size.width,
+            height: size.height,
+            format: image
+                .format()
+                .and_then(|format| format.extensions_str().first())
+                .map(|ext| (*ext).to_string()),
+            color_type: pixels.color_type,
+            bit_depth: pixels.bit_depth,
+            has_alpha: pixels.has_alpha,
+            frame_count: image.frame_count(),
+            file_size,
+            exif: image
+                .metadata()
+                .exif
+                .as_ref()
+                .and_then(|exif| exif_fields(exif).ok())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+fn exif_fields(exif: &[u8]) -> Result<BTreeMap<String, String>, exif::Error> {
+    let exif = exif::Reader::new().read_raw(exif.to_vec())?;
+    Ok(EXIF_TAGS
+        .iter()
+        .filter_map(|&tag| exif.get_field(tag, exif::In::PRIMARY))
+        .map(|field| {
+            let value = field.display_value().with_unit(&exif).to_string();
+            (field.tag.to_string(), value.trim_matches('"').to_string())
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Info;
+    use crate::image::Image;
+    use crate::metadata::{tests::exif, Metadata};
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn info() {
+        let image = image::DynamicImage::ImageLumaA16(image::ImageBuffer::new(30, 20));
+        let mut encoded = std::io::Cursor::new(Vec::new());
+        image
+            .write_to(&mut encoded, image::ImageOutputFormat::Png)
+            .unwrap();
+        let metadata = Metadata {
+            exif: Some(exif(&[
+                exif::Field {
+                    tag: exif::Tag::Model,
+                    ifd_num: exif::In::PRIMARY,
+                    value: exif::Value::Ascii(vec![b"Camera".to_vec()]),
+                },
+                exif::Field {
+                    tag: exif::Tag::FNumber,
+                    ifd_num: exif::In::PRIMARY,
+                    value: exif::Value::Rational(vec![(28, 10).into()]),
+                },
+                exif::Field {
+                    tag: exif::Tag::GPSLatitude,
+                    ifd_num: exif::In::PRIMARY,
+                    value: exif::Value::Rational(vec![(1, 1).into(); 3]),
+                },
+            ])),
+            ..Metadata::default()
+        };
+        let mut file = Vec::new();
+        metadata.embed(encoded.into_inner(), &mut file).unwrap();
+
+        let img = Image::new(std::io::Cursor::new(&file)).unwrap();
+        let info = Info::new(&img, file.len() as u64);
+        assert_eq!(
+            info,
+            Info {
+                width: 30,
+                height: 20,
+                format: Some("png".to_string()),
+                color_type: "la16".to_string(),
+                bit_depth: 16,
+                has_alpha: true,
+                frame_count: 1,
+                file_size: file.len() as u64,
+                exif: [
+                    ("FNumber".to_string(), "f/2.8".to_string()),
+                    ("Model".to_string(), "Camera".to_string()),
+                ]
+                .into_iter()
+                .collect(),
+            }
+        );
+    }
+}
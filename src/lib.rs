@@ -2,9 +2,14 @@
 #![allow(clippy::missing_errors_doc)]
 #![allow(clippy::missing_panics_doc)]
 
+pub mod adjustments;
+pub mod animation;
+pub mod backends;
 pub mod bounds;
 #[cfg(feature = "cache")]
 pub mod cache;
+pub mod codecs;
+pub mod color;
 #[cfg(feature = "compression")]
 pub mod compression;
 pub mod conditionals;
@@ -12,9 +17,20 @@ pub mod conditionals;
 pub mod content_type_filter;
 mod debug;
 pub mod file;
+pub mod filters;
 pub mod headers;
 pub mod image;
+pub mod info;
+pub mod limits;
+pub mod metadata;
 pub mod mime;
+pub mod orientation;
+pub mod palette;
+pub mod params;
+pub mod placeholder;
+pub mod quality;
+pub mod smartcrop;
+pub mod watermark;
 
 use warp::Filter;
 
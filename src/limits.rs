@@ -0,0 +1,164 @@
+use warp::http::StatusCode;
+
+/// Resource limit of decoding.
+#[derive(Eq, PartialEq, Hash, Debug, Clone, Copy)]
+pub enum Limit {
+    /// size of the encoded input in bytes
+    InputBytes,
+    Width,
+    Height,
+    /// number of pixels of a frame
+    Pixels,
+    /// memory allocated for the decoded frames in bytes
+    Allocation,
+}
+
+impl Limit {
+    /// Status of responses to requests exceeding the limit.
+    ///
+    /// Inputs that are too large are rejected with `413 Payload Too Large`,
+    /// images that would be too large once decoded with
+    /// `422 Unprocessable Entity`.
+    #[inline]
+    #[must_use]
+    pub fn status_code(self) -> StatusCode {
+        match self {
+            Self::InputBytes => StatusCode::PAYLOAD_TOO_LARGE,
+            _ => StatusCode::UNPROCESSABLE_ENTITY,
+        }
+    }
+}
+
+impl std::fmt::Display for Limit {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::InputBytes => "input size",
+            Self::Width => "width",
+            Self::Height => "height",
+            Self::Pixels => "pixel count",
+            Self::Allocation => "allocation",
+        })
+    }
+}
+
+/// Violation of a limit.
+#[derive(thiserror::Error, Eq, PartialEq, Debug, Clone, Copy)]
+#[error("{limit} exceeds the limit of {max}")]
+pub struct LimitExceeded {
+    pub limit: Limit,
+    pub max: u64,
+}
+
+/// Limits of untrusted images, checked from the header before decoding.
+///
+/// Limits set to `None` are not enforced.
+#[derive(Eq, PartialEq, Hash, Debug, Clone, Copy)]
+pub struct Limits {
+    /// largest size of the encoded input in bytes
+    pub max_input_bytes: Option<u64>,
+    /// largest width in pixels
+    pub max_width: Option<u32>,
+    /// largest height in pixels
+    pub max_height: Option<u32>,
+    /// largest number of pixels of a frame
+    pub max_pixels: Option<u64>,
+    /// largest memory allocation for the decoded frames in bytes
+    pub max_alloc: Option<u64>,
+}
+
+impl Limits {
+    /// No limits, for trusted images only.
+    pub const NONE: Self = Self {
+        max_input_bytes: None,
+        max_width: None,
+        max_height: None,
+        max_pixels: None,
+        max_alloc: None,
+    };
+
+    /// Checks the size of the encoded input.
+    ///
+    /// # Errors
+    ///
+    /// If the input is larger than the limit.
+    #[inline]
+    pub fn check_input(&self, bytes: u64) -> Result<(), LimitExceeded> {
+        check(Limit::InputBytes, bytes, self.max_input_bytes)
+    }
+
+    /// Checks the dimensions of a frame.
+    ///
+    /// # Errors
+    ///
+    /// If a dimension or the pixel count is larger than the limit.
+    #[inline]
+    pub fn check_dimensions(&self, width: u32, height: u32) -> Result<(), LimitExceeded> {
+        check(Limit::Width, width.into(), self.max_width.map(u64::from))?;
+        check(Limit::Height, height.into(), self.max_height.map(u64::from))?;
+        let pixels = u64::from(width) * u64::from(height);
+        check(Limit::Pixels, pixels, self.max_pixels)
+    }
+
+    /// Checks the memory allocated for decoded frames.
+    ///
+    /// # Errors
+    ///
+    /// If more bytes than the limit are allocated.
+    #[inline]
+    pub fn check_alloc(&self, bytes: u64) -> Result<(), LimitExceeded> {
+        check(Limit::Allocation, bytes, self.max_alloc)
+    }
+}
+
+impl Default for Limits {
+    /// Limits suitable for public endpoints.
+    ///
+    /// Inputs of up to 50 MiB and frames of up to 16384 pixels per dimension
+    /// and 100 megapixels are accepted, using at most 1 GiB once decoded.
+    #[inline]
+    fn default() -> Self {
+        Self {
+            max_input_bytes: Some(50 * 1024 * 1024),
+            max_width: Some(16_384),
+            max_height: Some(16_384),
+            max_pixels: Some(100_000_000),
+            max_alloc: Some(1024 * 1024 * 1024),
+        }
+    }
+}
+
+#[inline]
+fn check(limit: Limit, value: u64, max: Option<u64>) -> Result<(), LimitExceeded> {
+    match max {
+        Some(max) if value > max => Err(LimitExceeded { limit, max }),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Limit, LimitExceeded, Limits};
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn check() {
+        let limits = Limits {
+            max_input_bytes: Some(100),
+            max_width: Some(20),
+            max_height: None,
+            max_pixels: Some(200),
+            max_alloc: None,
+        };
+        let exceeded = |limit, max| Err(LimitExceeded { limit, max });
+        assert_eq!(limits.check_input(100), Ok(()));
+        assert_eq!(limits.check_input(101), exceeded(Limit::InputBytes, 100));
+        assert_eq!(limits.check_dimensions(20, 10), Ok(()));
+        assert_eq!(limits.check_dimensions(21, 1), exceeded(Limit::Width, 20));
+        assert_eq!(
+            limits.check_dimensions(10, 21),
+            exceeded(Limit::Pixels, 200)
+        );
+        assert_eq!(Limits::NONE.check_dimensions(u32::MAX, u32::MAX), Ok(()));
+        assert_eq!(limits.check_alloc(u64::MAX), Ok(()));
+    }
+}
@@ -0,0 +1,412 @@
+use bytes::Bytes;
+use img_parts::{DynImage, ImageEXIF, ImageICC};
+use serde::Deserialize;
+use std::io::Write;
+
+const JPEG_APP1: u8 = 0xE1;
+const JPEG_XMP_PREFIX: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+const PNG_ITXT: [u8; 4] = *b"iTXt";
+const PNG_XMP_KEYWORD: &[u8] = b"XML:com.adobe.xmp\0";
+/// Prefix of the names of qualified XMP properties of GPS positions, e.g. `exif:GPSLatitude`.
+const GPS_PROPERTY: &[u8] = b":GPS";
+/// Position of the color space signature in the header of ICC profiles.
+const ICC_COLOR_SPACE: std::ops::Range<usize> = 16..20;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("container error: `{0}`")]
+    Container(#[from] img_parts::Error),
+
+    #[error("exif error: `{0}`")]
+    Exif(#[from] exif::Error),
+
+    #[error("io error: `{0}`")]
+    Io(#[from] std::io::Error),
+}
+
+/// Policy for carrying metadata of the source over to the encoded output.
+///
+/// GPS positions are never carried over.
+#[derive(Deserialize, Eq, PartialEq, Hash, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum MetadataPolicy {
+    /// Drop all metadata.
+    StripAll,
+    /// Keep the ICC color profile only.
+    #[default]
+    KeepIcc,
+    /// Keep the ICC color profile and the copyright and artist EXIF fields.
+    KeepCopyright,
+    /// Keep the ICC color profile, EXIF and XMP.
+    ///
+    /// XMP packets with GPS properties such as `exif:GPSLatitude` are
+    /// dropped completely.
+    KeepAll,
+}
+
+/// Color space of encoded pixels, which an embedded ICC profile has to describe.
+#[derive(Eq, PartialEq, Hash, Debug, Clone, Copy)]
+pub enum ColorSpace {
+    Rgb,
+    Gray,
+}
+
+impl ColorSpace {
+    /// Signature of the color space in ICC profile headers.
+    fn icc_signature(self) -> &'static [u8] {
+        match self {
+            Self::Rgb => b"RGB ",
+            Self::Gray => b"GRAY",
+        }
+    }
+}
+
+/// Metadata segments of an image container.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Metadata {
+    /// ICC color profile
+    pub icc: Option<Bytes>,
+    /// EXIF data in TIFF format
+    pub exif: Option<Bytes>,
+    /// XMP packet
+    pub xmp: Option<Bytes>,
+}
+
+impl Metadata {
+    /// Reads the metadata of a JPEG, PNG or WebP container that the policy
+    /// may keep, sharing the segments with the data.
+    pub fn read(data: &Bytes, policy: MetadataPolicy) -> Self {
+        if policy == MetadataPolicy::StripAll {
+            return Self::default();
+        }
+        // unknown or corrupt containers are reported by the decoder
+        match DynImage::from_bytes(data.clone()) {
+            Ok(Some(image)) if policy == MetadataPolicy::KeepIcc => Self {
+                icc: image.icc_profile(),
+                ..Self::default()
+            },
+            Ok(Some(image)) => Self::from_container(&image),
+            _ => Self::default(),
+        }
+    }
+
+    fn from_container(image: &DynImage) -> Self {
+        let xmp = match image {
+            DynImage::Jpeg(jpeg) => jpeg.segments_by_marker(JPEG_APP1).find_map(|segment| {
+                let contents = segment.contents();
+                contents
+                    .starts_with(JPEG_XMP_PREFIX)
+                    .then(|| contents.slice(JPEG_XMP_PREFIX.len()..))
+            }),
+            DynImage::Png(png) => png
+                .chunks_by_type(PNG_ITXT)
+                .find_map(|chunk| png_xmp(chunk.contents())),
+            DynImage::WebP(_) => None,
+        };
+        Self {
+            icc: image.icc_profile(),
+            exif: image.exif(),
+            xmp,
+        }
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.icc.is_none() && self.exif.is_none() && self.xmp.is_none()
+    }
+
+    /// Selects the metadata to keep according to the policy.
+    ///
+    /// EXIF fields describing the GPS position, the orientation or the layout
+    /// of the source pixels are always removed. Unparseable EXIF data is
+    /// treated as absent.
+    pub fn filter(&self, policy: MetadataPolicy) -> Result<Self, Error> {
+        let keep_tag: fn(exif::Tag) -> bool = match policy {
+            MetadataPolicy::StripAll => return Ok(Self::default()),
+            MetadataPolicy::KeepIcc => {
+                return Ok(Self {
+                    icc: self.icc.clone(),
+                    ..Self::default()
+                })
+            }
+            MetadataPolicy::KeepCopyright => {
+                |tag| matches!(tag, exif::Tag::Copyright | exif::Tag::Artist)
+            }
+            MetadataPolicy::KeepAll => |tag| {
+                !matches!(
+                    tag,
+                    exif::Tag::Orientation
+                        | exif::Tag::ImageWidth
+                        | exif::Tag::ImageLength
+                        | exif::Tag::BitsPerSample
+                        | exif::Tag::Compression
+                        | exif::Tag::PhotometricInterpretation
+                        | exif::Tag::SamplesPerPixel
+                        | exif::Tag::RowsPerStrip
+                        | exif::Tag::PlanarConfiguration
+                        | exif::Tag::PixelXDimension
+                        | exif::Tag::PixelYDimension
+                )
+            },
+        };
+        let exif = match self.exif {
+            Some(ref exif) => filter_exif(exif, keep_tag)?,
+            None => None,
+        };
+        let xmp = match policy {
+            MetadataPolicy::KeepAll => self
+                .xmp
+                .clone()
+                .filter(|xmp| !xmp.windows(GPS_PROPERTY.len()).any(|w| w == GPS_PROPERTY)),
+            _ => None,
+        };
+        Ok(Self {
+            icc: self.icc.clone(),
+            exif,
+            xmp,
+        })
+    }
+
+    /// Drops an ICC profile that does not describe the color space of the
+    /// encoded pixels, e.g. the CMYK profile of a source converted to RGB.
+    #[must_use]
+    pub fn for_color_space(mut self, color_space: ColorSpace) -> Self {
+        let matches = |icc: &Bytes| icc.get(ICC_COLOR_SPACE) == Some(color_space.icc_signature());
+        self.icc = self.icc.filter(matches);
+        self
+    }
+
+    /// Embeds the metadata into an encoded JPEG, PNG or WebP image.
+    ///
+    /// Other containers are written unchanged. XMP is not embedded into WebP.
+    pub fn embed<W: Write>(&self, encoded: Vec<u8>, w: &mut W) -> Result<(), Error> {
+        let encoded = Bytes::from(encoded);
+        let mut image = match DynImage::from_bytes(encoded.clone())? {
+            Some(image) => image,
+            None => {
+                w.write_all(&encoded)?;
+                return Ok(());
+            }
+        };
+        image.set_icc_profile(self.icc.clone());
+        image.set_exif(self.exif.clone());
+        if let Some(ref xmp) = self.xmp {
+            match image {
+                DynImage::Jpeg(ref mut jpeg) => {
+                    let mut contents = JPEG_XMP_PREFIX.to_vec();
+                    contents.extend_from_slice(xmp);
+                    let segments = jpeg.segments_mut();
+                    // place after the other application segments
+                    let pos = segments
+                        .iter()
+                        .position(|segment| !(0xE0..=0xEF).contains(&segment.marker()))
+                        .unwrap_or(segments.len());
+                    segments.insert(
+                        pos,
+                        img_parts::jpeg::JpegSegment::new_with_contents(JPEG_APP1, contents.into()),
+                    );
+                }
+                DynImage::Png(ref mut png) => {
+                    let mut contents = PNG_XMP_KEYWORD.to_vec();
+                    // uncompressed, no language tag and translated keyword
+                    contents.extend_from_slice(&[0, 0, 0, 0]);
+                    contents.extend_from_slice(xmp);
+                    // place right after the header chunk
+                    png.chunks_mut()
+                        .insert(1, img_parts::png::PngChunk::new(PNG_ITXT, contents.into()));
+                }
+                DynImage::WebP(_) => {}
+            }
+        }
+        image.encoder().write_to(w)?;
+        Ok(())
+    }
+}
+
+/// Extracts an uncompressed XMP packet from a PNG `iTXt` chunk.
+fn png_xmp(contents: &Bytes) -> Option<Bytes> {
+    let rest = contents.strip_prefix(PNG_XMP_KEYWORD)?;
+    let (&compressed, rest) = rest.split_first()?;
+    if compressed != 0 {
+        return None;
+    }
+    // skip compression method, language tag and translated keyword
+    let mut rest = rest.get(1..)?;
+    for _ in 0..2 {
+        let end = rest.iter().position(|&b| b == 0)?;
+        rest = &rest[end + 1..];
+    }
+    Some(contents.slice(contents.len() - rest.len()..))
+}
+
+fn filter_exif(exif: &Bytes, keep_tag: fn(exif::Tag) -> bool) -> Result<Option<Bytes>, Error> {
+    let exif = match exif::Reader::new().read_raw(exif.to_vec()) {
+        Ok(exif) => exif,
+        Err(_) => return Ok(None),
+    };
+    let fields: Vec<&exif::Field> = exif
+        .fields()
+        .filter(|field| field.ifd_num == exif::In::PRIMARY)
+        .filter(|field| field.tag.context() != exif::Context::Gps)
+        .filter(|field| keep_tag(field.tag))
+        .collect();
+    if fields.is_empty() {
+        return Ok(None);
+    }
+    let mut writer = exif::experimental::Writer::new();
+    for field in fields {
+        writer.push_field(field);
+    }
+    let mut buf = std::io::Cursor::new(Vec::new());
+    writer.write(&mut buf, exif.little_endian())?;
+    Ok(Some(buf.into_inner().into()))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::{ColorSpace, Metadata, MetadataPolicy};
+    use pretty_assertions::assert_eq;
+
+    /// ICC profile header for the color space signature
+    fn icc(color_space: &[u8; 4]) -> bytes::Bytes {
+        let mut header = vec![0; 128];
+        header[16..20].copy_from_slice(color_space);
+        header.into()
+    }
+
+    /// EXIF data in TIFF format with the given fields
+    pub(crate) fn exif(fields: &[exif::Field]) -> bytes::Bytes {
+        let mut writer = exif::experimental::Writer::new();
+        for field in fields {
+            writer.push_field(field);
+        }
+        let mut buf = std::io::Cursor::new(Vec::new());
+        writer.write(&mut buf, false).unwrap();
+        buf.into_inner().into()
+    }
+
+    fn ascii(tag: exif::Tag, value: &str) -> exif::Field {
+        exif::Field {
+            tag,
+            ifd_num: exif::In::PRIMARY,
+            value: exif::Value::Ascii(vec![value.as_bytes().to_vec()]),
+        }
+    }
+
+    fn tags(metadata: &Metadata) -> Vec<exif::Tag> {
+        let raw = metadata.exif.as_ref().unwrap().to_vec();
+        let exif = exif::Reader::new().read_raw(raw).unwrap();
+        exif.fields().map(|field| field.tag).collect()
+    }
+
+    fn source() -> Metadata {
+        Metadata {
+            icc: Some(icc(b"RGB ")),
+            exif: Some(exif(&[
+                ascii(exif::Tag::Copyright, "romnn"),
+                ascii(exif::Tag::Make, "camera"),
+                ascii(exif::Tag::GPSLatitudeRef, "N"),
+                exif::Field {
+                    tag: exif::Tag::Orientation,
+                    ifd_num: exif::In::PRIMARY,
+                    value: exif::Value::Short(vec![6]),
+                },
+            ])),
+            xmp: Some(b"<x:xmpmeta/>".to_vec().into()),
+        }
+    }
+
+    #[test]
+    fn strip_all() {
+        let filtered = source().filter(MetadataPolicy::StripAll).unwrap();
+        assert!(filtered.is_empty());
+    }
+
+    #[test]
+    fn keep_icc() {
+        let filtered = source().filter(MetadataPolicy::KeepIcc).unwrap();
+        assert_eq!(filtered.icc, source().icc);
+        assert_eq!(filtered.exif, None);
+        assert_eq!(filtered.xmp, None);
+    }
+
+    #[test]
+    fn icc_color_space() {
+        let metadata = source().filter(MetadataPolicy::KeepIcc).unwrap();
+        assert_eq!(metadata.clone().for_color_space(ColorSpace::Rgb), metadata);
+        assert_eq!(metadata.for_color_space(ColorSpace::Gray).icc, None);
+
+        let cmyk = Metadata {
+            icc: Some(icc(b"CMYK")),
+            ..Metadata::default()
+        };
+        assert!(cmyk.for_color_space(ColorSpace::Rgb).is_empty());
+        let truncated = Metadata {
+            icc: Some(b"icc".to_vec().into()),
+            ..Metadata::default()
+        };
+        assert!(truncated.for_color_space(ColorSpace::Rgb).is_empty());
+    }
+
+    #[test]
+    fn keep_copyright() {
+        let filtered = source().filter(MetadataPolicy::KeepCopyright).unwrap();
+        assert_eq!(tags(&filtered), vec![exif::Tag::Copyright]);
+        assert_eq!(filtered.xmp, None);
+    }
+
+    #[test]
+    fn keep_all_without_gps() {
+        let filtered = source().filter(MetadataPolicy::KeepAll).unwrap();
+        let mut tags = tags(&filtered);
+        tags.sort_by_key(|tag| tag.number());
+        assert_eq!(tags, vec![exif::Tag::Make, exif::Tag::Copyright]);
+        assert_eq!(filtered.xmp, source().xmp);
+    }
+
+    #[test]
+    fn corrupt_exif() {
+        let metadata = Metadata {
+            exif: Some(b"not exif".to_vec().into()),
+            ..source()
+        };
+        let filtered = metadata.filter(MetadataPolicy::KeepAll).unwrap();
+        assert_eq!(filtered.exif, None);
+        assert_eq!(filtered.icc, source().icc);
+    }
+
+    #[test]
+    fn xmp_with_gps() {
+        let xmp = |packet: &str| Metadata {
+            xmp: Some(packet.as_bytes().to_vec().into()),
+            ..source()
+        };
+        let description = xmp(r#"<dc:description>GPS tracker</dc:description>"#);
+        let filtered = description.filter(MetadataPolicy::KeepAll).unwrap();
+        assert_eq!(filtered.xmp, description.xmp);
+
+        let position = xmp(r#"<rdf:Description exif:GPSLatitude="52,31.2N"/>"#);
+        let filtered = position.filter(MetadataPolicy::KeepAll).unwrap();
+        assert_eq!(filtered.xmp, None);
+    }
+
+    #[test]
+    fn embed_roundtrip() {
+        use image::ImageEncoder;
+        let mut encoded = Vec::new();
+        image::codecs::png::PngEncoder::new(&mut encoded)
+            .write_image(&[0, 0, 0], 1, 1, image::ColorType::Rgb8)
+            .unwrap();
+        let metadata = source().filter(MetadataPolicy::KeepAll).unwrap();
+
+        let mut output = Vec::new();
+        metadata.embed(encoded, &mut output).unwrap();
+        let output = output.into();
+        assert_eq!(Metadata::read(&output, MetadataPolicy::KeepAll), metadata);
+        let icc = Metadata::read(&output, MetadataPolicy::KeepIcc);
+        assert_eq!(icc.icc, metadata.icc);
+        assert_eq!((icc.exif, icc.xmp), (None, None));
+        assert!(Metadata::read(&output, MetadataPolicy::StripAll).is_empty());
+    }
+}
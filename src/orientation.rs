@@ -0,0 +1,152 @@
+use image::DynamicImage;
+use serde::Deserialize;
+use std::io::{BufRead, Seek, SeekFrom};
+
+/// Clockwise rotation of the image.
+#[derive(Deserialize, Eq, PartialEq, Hash, Debug, Clone, Copy)]
+pub enum Rotation {
+    #[serde(rename = "90")]
+    Rotate90,
+    #[serde(rename = "180")]
+    Rotate180,
+    #[serde(rename = "270")]
+    Rotate270,
+}
+
+/// Mirroring of the image.
+#[derive(Deserialize, Eq, PartialEq, Hash, Debug, Clone, Copy)]
+pub enum Flip {
+    /// mirror along the vertical axis
+    #[serde(rename = "h")]
+    Horizontal,
+    /// mirror along the horizontal axis
+    #[serde(rename = "v")]
+    Vertical,
+}
+
+impl Rotation {
+    #[inline]
+    #[must_use]
+    pub fn apply(self, image: &DynamicImage) -> DynamicImage {
+        match self {
+            Self::Rotate90 => image.rotate90(),
+            Self::Rotate180 => image.rotate180(),
+            Self::Rotate270 => image.rotate270(),
+        }
+    }
+}
+
+impl Flip {
+    #[inline]
+    #[must_use]
+    pub fn apply(self, image: &DynamicImage) -> DynamicImage {
+        match self {
+            Self::Horizontal => image.fliph(),
+            Self::Vertical => image.flipv(),
+        }
+    }
+}
+
+/// Orientation as defined by the EXIF `Orientation` tag.
+///
+/// Describes the transformation to apply to the stored pixels so that the image
+/// is displayed upright.
+#[derive(Eq, PartialEq, Hash, Debug, Clone, Copy, Default)]
+pub struct Orientation {
+    pub rotate: Option<Rotation>,
+    pub flip: Option<Flip>,
+}
+
+impl Orientation {
+    /// Parses the EXIF orientation value (1 to 8).
+    #[inline]
+    #[must_use]
+    pub fn from_exif(value: u32) -> Option<Self> {
+        let (rotate, flip) = match value {
+            1 => (None, None),
+            2 => (None, Some(Flip::Horizontal)),
+            3 => (Some(Rotation::Rotate180), None),
+            4 => (None, Some(Flip::Vertical)),
+            5 => (Some(Rotation::Rotate90), Some(Flip::Horizontal)),
+            6 => (Some(Rotation::Rotate90), None),
+            7 => (Some(Rotation::Rotate270), Some(Flip::Horizontal)),
+            8 => (Some(Rotation::Rotate270), None),
+            _ => return None,
+        };
+        Some(Self { rotate, flip })
+    }
+
+    /// Reads the orientation from the EXIF data of a JPEG, TIFF, PNG or WebP container.
+    ///
+    /// The reader is rewound to its initial position afterwards.
+    pub fn read<R: BufRead + Seek>(reader: &mut R) -> std::io::Result<Option<Self>> {
+        let start = reader.stream_position()?;
+        let exif = exif::Reader::new().read_from_container(reader);
+        reader.seek(SeekFrom::Start(start))?;
+        Ok(exif.ok().and_then(|exif| {
+            exif.get_field(exif::Tag::Orientation, exif::In::PRIMARY)
+                .and_then(|field| field.value.get_uint(0))
+                .and_then(Self::from_exif)
+        }))
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn is_identity(&self) -> bool {
+        self.rotate.is_none() && self.flip.is_none()
+    }
+
+    /// Rotates and then flips the image.
+    #[inline]
+    #[must_use]
+    pub fn apply(&self, image: DynamicImage) -> DynamicImage {
+        let image = match self.rotate {
+            Some(rotate) => rotate.apply(&image),
+            None => image,
+        };
+        match self.flip {
+            Some(flip) => flip.apply(&image),
+            None => image,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Orientation;
+    use image::{DynamicImage, GenericImageView, GrayImage, Luma};
+    use pretty_assertions::assert_eq;
+
+    /// 3x2 image where each pixel value encodes its position
+    fn image() -> DynamicImage {
+        DynamicImage::ImageLuma8(GrayImage::from_fn(3, 2, |x, y| {
+            Luma([u8::try_from(y * 3 + x).unwrap()])
+        }))
+    }
+
+    fn pixels(image: &DynamicImage) -> ((u32, u32), Vec<u8>) {
+        (image.dimensions(), image.to_luma8().into_raw())
+    }
+
+    #[test]
+    fn exif_orientations() {
+        let oriented = |value| pixels(&Orientation::from_exif(value).unwrap().apply(image()));
+        assert_eq!(oriented(1), ((3, 2), vec![0, 1, 2, 3, 4, 5]));
+        assert_eq!(oriented(2), ((3, 2), vec![2, 1, 0, 5, 4, 3]));
+        assert_eq!(oriented(3), ((3, 2), vec![5, 4, 3, 2, 1, 0]));
+        assert_eq!(oriented(4), ((3, 2), vec![3, 4, 5, 0, 1, 2]));
+        assert_eq!(oriented(5), ((2, 3), vec![0, 3, 1, 4, 2, 5]));
+        assert_eq!(oriented(6), ((2, 3), vec![3, 0, 4, 1, 5, 2]));
+        assert_eq!(oriented(7), ((2, 3), vec![5, 2, 4, 1, 3, 0]));
+        assert_eq!(oriented(8), ((2, 3), vec![2, 5, 1, 4, 0, 3]));
+        assert_eq!(Orientation::from_exif(9), None);
+    }
+
+    #[test]
+    fn missing_exif() {
+        let mut reader = std::io::Cursor::new(vec![0_u8; 16]);
+        reader.set_position(4);
+        assert_eq!(Orientation::read(&mut reader).unwrap(), None);
+        assert_eq!(reader.position(), 4);
+    }
+}
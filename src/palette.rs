@@ -0,0 +1,160 @@
+use super::color::Color;
+use image::DynamicImage;
+use serde::Serialize;
+
+/// Number of palette colors if not requested otherwise.
+pub const DEFAULT_COLORS: usize = 5;
+/// Largest number of palette colors.
+pub const MAX_COLORS: usize = 16;
+/// Largest dimension the image is reduced to before sampling its colors.
+const SAMPLE_SIZE: u32 = 100;
+
+/// Color of a palette and the fraction of the image it represents.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Swatch {
+    pub color: Color,
+    /// fraction of the opaque pixels from 0 to 1
+    pub population: f32,
+}
+
+/// Representative colors of an image, most common first.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Palette {
+    /// most common color, if the image has opaque pixels
+    pub dominant: Option<Color>,
+    pub colors: Vec<Swatch>,
+}
+
+impl Palette {
+    /// Extracts up to the given number of colors using median cut.
+    ///
+    /// Mostly transparent pixels are ignored. Fewer colors are returned if
+    /// the image has fewer distinct colors.
+    #[must_use]
+    pub fn new(image: &DynamicImage, colors: usize) -> Self {
+        let colors = colors.clamp(1, MAX_COLORS);
+        let sample = if image.width() > SAMPLE_SIZE || image.height() > SAMPLE_SIZE {
+            image.thumbnail(SAMPLE_SIZE, SAMPLE_SIZE).into_rgba8()
+        } else {
+            image.to_rgba8()
+        };
+        let pixels: Vec<[u8; 3]> = sample
+            .pixels()
+            .filter(|p| p[3] >= 128)
+            .map(|p| [p[0], p[1], p[2]])
+            .collect();
+        let total = pixels.len();
+
+        let mut boxes = vec![pixels];
+        while boxes.len() < colors {
+            // split the box with the widest range of a channel at its median,
+            // keeping equal values together
+            let widest = boxes
+                .iter()
+                .enumerate()
+                .map(|(index, pixels)| (index, widest_channel(pixels)))
+                .filter(|(_, (_, range))| *range > 0)
+                .max_by_key(|(_, (_, range))| *range);
+            let (index, channel) = match widest {
+                Some((index, (channel, _))) => (index, channel),
+                None => break,
+            };
+            let mut lower = boxes.swap_remove(index);
+            lower.sort_unstable_by_key(|pixel| pixel[channel]);
+            let median = lower[lower.len() / 2][channel];
+            let mut split = lower.partition_point(|pixel| pixel[channel] <= median);
+            if split == lower.len() {
+                split = lower.partition_point(|pixel| pixel[channel] < median);
+            }
+            let upper = lower.split_off(split);
+            boxes.push(lower);
+            boxes.push(upper);
+        }
+
+        boxes.retain(|pixels| !pixels.is_empty());
+        boxes.sort_by_key(|pixels| std::cmp::Reverse(pixels.len()));
+        let colors: Vec<Swatch> = boxes
+            .iter()
+            .map(|pixels| {
+                let len = pixels.len() as u64;
+                let mean = |channel: usize| {
+                    let sum: u64 = pixels.iter().map(|pixel| u64::from(pixel[channel])).sum();
+                    // the mean of bytes is a byte
+                    #[allow(clippy::cast_possible_truncation)]
+                    let mean = ((sum + len / 2) / len) as u8;
+                    mean
+                };
+                // pixel counts are far below the precision limits of f32
+                #[allow(clippy::cast_precision_loss)]
+                let population = pixels.len() as f32 / total as f32;
+                Swatch {
+                    color: Color([mean(0), mean(1), mean(2), u8::MAX]),
+                    population,
+                }
+            })
+            .collect();
+        Self {
+            dominant: colors.first().map(|swatch| swatch.color),
+            colors,
+        }
+    }
+}
+
+/// Channel with the widest range of values and its range.
+fn widest_channel(pixels: &[[u8; 3]]) -> (usize, u8) {
+    (0..3)
+        .map(|channel| {
+            let values = pixels.iter().map(|pixel| pixel[channel]);
+            let min = values.clone().min().unwrap_or_default();
+            let max = values.max().unwrap_or_default();
+            (channel, max - min)
+        })
+        .max_by_key(|(_, range)| *range)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Palette, Swatch};
+    use crate::color::Color;
+    use image::{DynamicImage, Rgba, RgbaImage};
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn median_cut() {
+        let image = DynamicImage::ImageRgba8(RgbaImage::from_fn(40, 40, |x, y| {
+            Rgba(match (x < 30, y < 20) {
+                (true, _) => [200, 0, 0, 255],
+                (false, true) => [0, 0, 200, 255],
+                (false, false) => [0, 0, 0, 0],
+            })
+        }));
+        let palette = Palette::new(&image, 5);
+        assert_eq!(palette.dominant, Some(Color([200, 0, 0, 255])));
+        assert_eq!(
+            palette.colors,
+            vec![
+                Swatch {
+                    color: Color([200, 0, 0, 255]),
+                    population: 6.0 / 7.0,
+                },
+                Swatch {
+                    color: Color([0, 0, 200, 255]),
+                    population: 1.0 / 7.0,
+                },
+            ]
+        );
+
+        let single = Palette::new(&image, 1);
+        assert_eq!(single.colors.len(), 1);
+        assert_eq!(single.colors[0].population, 1.0);
+    }
+
+    #[test]
+    fn transparent() {
+        let image = DynamicImage::ImageRgba8(RgbaImage::new(8, 8));
+        let palette = Palette::new(&image, 3);
+        assert_eq!(palette.dominant, None);
+        assert!(palette.colors.is_empty());
+    }
+}
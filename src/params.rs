@@ -0,0 +1,104 @@
+//! Helpers for parsing query parameters.
+
+/// Non-negative fixed-point parameter with the given number of decimal places.
+///
+/// The value is quantized so that it can be part of cache keys.
+#[derive(Eq, PartialEq, Ord, PartialOrd, Hash, Debug, Clone, Copy)]
+pub struct Decimal<const PLACES: u32>(u32);
+
+/// Parameter in hundredths.
+pub type Hundredths = Decimal<2>;
+
+impl<const PLACES: u32> Decimal<PLACES> {
+    const SCALE: u32 = 10_u32.pow(PLACES);
+
+    /// Value of the given number of units in the last place, e.g. 150 hundredths.
+    #[inline]
+    #[must_use]
+    pub const fn new(units: u32) -> Self {
+        Self(units)
+    }
+
+    /// Number of units in the last place.
+    #[inline]
+    #[must_use]
+    pub fn units(self) -> u32 {
+        self.0
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn value(self) -> f32 {
+        // parameters are bounded, hence the cast is exact enough
+        #[allow(clippy::cast_precision_loss)]
+        let value = self.0 as f32 / Self::SCALE as f32;
+        value
+    }
+
+    pub(crate) fn parse(s: &str, name: &str, max: f32) -> Result<Self, String> {
+        Self::parse_in(s, name, 0.0, max)
+    }
+
+    pub(crate) fn parse_in(s: &str, name: &str, min: f32, max: f32) -> Result<Self, String> {
+        let invalid = || format!("invalid {} `{}`, expected {} to {}", name, s, min, max);
+        let value: f32 = s.trim().parse().map_err(|_| invalid())?;
+        if !(min..=max).contains(&value) {
+            return Err(invalid());
+        }
+        // the value is in range, hence the cast cannot truncate
+        #[allow(
+            clippy::cast_possible_truncation,
+            clippy::cast_sign_loss,
+            clippy::cast_precision_loss
+        )]
+        let units = (value * Self::SCALE as f32).round() as u32;
+        Ok(Self(units))
+    }
+}
+
+impl<const PLACES: u32> std::fmt::Display for Decimal<PLACES> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let (integer, fraction) = (self.0 / Self::SCALE, self.0 % Self::SCALE);
+        write!(f, "{}.{:02$}", integer, fraction, PLACES as usize)
+    }
+}
+
+macro_rules! deserialize_from_str {
+    ($($ty:ty),*) => {
+        $(
+            impl<'de> serde::Deserialize<'de> for $ty {
+                fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+                where
+                    D: serde::Deserializer<'de>,
+                {
+                    let s: std::borrow::Cow<'de, str> = serde::Deserialize::deserialize(deserializer)?;
+                    s.parse().map_err(serde::de::Error::custom)
+                }
+            }
+        )*
+    };
+}
+
+pub(crate) use deserialize_from_str;
+
+#[cfg(test)]
+mod tests {
+    use super::{Decimal, Hundredths};
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn parse() {
+        let parse = |s| Hundredths::parse(s, "value", 2.0).map(|value| value.to_string());
+        assert_eq!(parse("1.234"), Ok("1.23".to_string()));
+        assert_eq!(parse(" 0 "), Ok("0.00".to_string()));
+        assert!(parse("2.5").is_err());
+        assert!(parse("-1").is_err());
+        assert!(Hundredths::parse_in("0.5", "value", 1.0, 2.0).is_err());
+
+        let fine = Decimal::<4>::parse("0.98765", "value", 1.0).unwrap();
+        assert_eq!(
+            (fine.units(), fine.to_string()),
+            (9877, "0.9877".to_string())
+        );
+    }
+}
@@ -0,0 +1,111 @@
+use super::codecs::jpeg;
+use super::color::Color;
+use image::{imageops::FilterType, DynamicImage, GenericImageView, ImageError};
+use serde::Serialize;
+
+/// Largest dimension of the low quality image placeholder.
+pub const LQIP_SIZE: u32 = 16;
+/// Quality of the low quality image placeholder.
+const LQIP_QUALITY: u8 = 50;
+/// Largest dimension the image is reduced to before computing the BlurHash,
+/// which is plenty for at most 4 components per dimension.
+const BLURHASH_SIZE: u32 = 64;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("image error: `{0}`")]
+    Image(#[from] ImageError),
+
+    #[error("blurhash error: `{0}`")]
+    BlurHash(#[from] blurhash::Error),
+}
+
+/// Placeholders to render while the full image is loading.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Placeholder {
+    /// [BlurHash](https://blurha.sh) of the image
+    pub blurhash: String,
+    /// tiny JPEG version of the image as a base64 data URL
+    pub lqip: String,
+    /// width of the image
+    pub width: u32,
+    /// height of the image
+    pub height: u32,
+}
+
+impl Placeholder {
+    /// Computes the placeholders of the image.
+    ///
+    /// The BlurHash uses 4 components along the longer dimension and 3 along
+    /// the shorter one.
+    ///
+    /// # Errors
+    ///
+    /// If the image is empty or the placeholder cannot be encoded.
+    pub fn new(image: &DynamicImage) -> Result<Self, Error> {
+        let (width, height) = image.dimensions();
+        let (components_x, components_y) = if width >= height { (4, 3) } else { (3, 4) };
+        let small = image
+            .resize(BLURHASH_SIZE, BLURHASH_SIZE, FilterType::Triangle)
+            .into_rgba8();
+        let blurhash = blurhash::encode(
+            components_x,
+            components_y,
+            small.width(),
+            small.height(),
+            small.as_raw(),
+        )?;
+
+        let mut lqip = image.resize(LQIP_SIZE, LQIP_SIZE, FilterType::Triangle);
+        if lqip.color().has_alpha() {
+            lqip = Color::WHITE.flatten(&lqip);
+        }
+        let mut encoded = Vec::new();
+        let settings = jpeg::Settings {
+            quality: LQIP_QUALITY,
+            ..jpeg::Settings::default()
+        };
+        jpeg::default_encoder().encode(&lqip, &mut encoded, settings)?;
+        let lqip = format!("data:image/jpeg;base64,{}", base64::encode(encoded));
+
+        Ok(Self {
+            blurhash,
+            lqip,
+            width,
+            height,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Placeholder, LQIP_SIZE};
+    use image::{DynamicImage, Rgba, RgbaImage};
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn placeholder() {
+        let image = DynamicImage::ImageRgba8(RgbaImage::from_fn(200, 100, |x, _| {
+            Rgba(if x < 100 {
+                [255, 0, 0, 255]
+            } else {
+                [0, 0, 255, 128]
+            })
+        }));
+        let placeholder = Placeholder::new(&image).unwrap();
+        assert_eq!((placeholder.width, placeholder.height), (200, 100));
+        // the first character encodes the number of components
+        assert!(placeholder.blurhash.starts_with('L'));
+        assert_eq!(placeholder.blurhash.len(), 4 + 2 * 4 * 3);
+
+        let data = placeholder
+            .lqip
+            .strip_prefix("data:image/jpeg;base64,")
+            .unwrap();
+        let lqip = image::load_from_memory(&base64::decode(data).unwrap()).unwrap();
+        assert_eq!((lqip.width(), lqip.height()), (LQIP_SIZE, LQIP_SIZE / 2));
+
+        let decoded = blurhash::decode(&placeholder.blurhash, 4, 2, 1.0).unwrap();
+        assert!(decoded[0] > decoded[2], "left is red");
+    }
+}
@@ -0,0 +1,171 @@
+use crate::params::{deserialize_from_str, Decimal};
+use image::{DynamicImage, GrayImage};
+
+/// Side length of the windows the structural similarity is computed over.
+const WINDOW: u32 = 8;
+/// Distance between neighboring windows.
+const STRIDE: u32 = 4;
+/// Stabilizing constants for 8 bit samples.
+const C1: f64 = (0.01 * 255.0) * (0.01 * 255.0);
+const C2: f64 = (0.03 * 255.0) * (0.03 * 255.0);
+
+/// Quality requested for lossy encodings.
+#[derive(Eq, PartialEq, Hash, Debug, Clone, Copy)]
+pub enum Quality {
+    /// lowest quality that keeps the output similar to the image
+    Auto,
+    /// quality value from 0 to 100
+    Exact(u8),
+}
+
+impl std::str::FromStr for Quality {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.eq_ignore_ascii_case("auto") {
+            return Ok(Self::Auto);
+        }
+        s.parse()
+            .ok()
+            .filter(|quality| *quality <= 100)
+            .map(Self::Exact)
+            .ok_or_else(|| format!("invalid quality `{}`, expected auto or 0 to 100", s))
+    }
+}
+
+deserialize_from_str!(Quality);
+
+/// Structural similarity between 0 and 1, in ten-thousandths.
+#[derive(Eq, PartialEq, Ord, PartialOrd, Hash, Debug, Clone, Copy)]
+pub struct Ssim(Decimal<4>);
+
+impl Ssim {
+    /// Similarity `quality=auto` aims for if not configured otherwise.
+    pub const DEFAULT: Self = Self(Decimal::new(9800));
+
+    #[inline]
+    #[must_use]
+    pub fn value(self) -> f64 {
+        f64::from(self.0.value())
+    }
+}
+
+impl Default for Ssim {
+    #[inline]
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+impl std::fmt::Display for Ssim {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl std::str::FromStr for Ssim {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Decimal::parse(s, "similarity", 1.0).map(Self)
+    }
+}
+
+deserialize_from_str!(Ssim);
+
+/// Mean structural similarity of the luma of two images of the same size.
+///
+/// The similarity is 1 for identical images and decreases with visible
+/// differences in luminance, contrast and structure. Images smaller than a
+/// window are compared as a whole.
+#[must_use]
+pub fn ssim(reference: &DynamicImage, distorted: &DynamicImage) -> f64 {
+    let reference = reference.to_luma8();
+    let distorted = distorted.to_luma8();
+    let width = reference.width().min(distorted.width());
+    let height = reference.height().min(distorted.height());
+    if width == 0 || height == 0 {
+        return 1.0;
+    }
+    let window_width = WINDOW.min(width);
+    let window_height = WINDOW.min(height);
+
+    let (mut total, mut count) = (0.0, 0_u32);
+    for y in (0..=height - window_height).step_by(STRIDE as usize) {
+        for x in (0..=width - window_width).step_by(STRIDE as usize) {
+            let window = (x, y, window_width, window_height);
+            total += window_ssim(&reference, &distorted, window);
+            count += 1;
+        }
+    }
+    total / f64::from(count)
+}
+
+fn window_ssim(
+    reference: &GrayImage,
+    distorted: &GrayImage,
+    (x, y, width, height): (u32, u32, u32, u32),
+) -> f64 {
+    let (mut sum_a, mut sum_b, mut sum_aa, mut sum_bb, mut sum_ab) = (0.0, 0.0, 0.0, 0.0, 0.0);
+    for py in y..y + height {
+        for px in x..x + width {
+            let a = f64::from(reference.get_pixel(px, py)[0]);
+            let b = f64::from(distorted.get_pixel(px, py)[0]);
+            sum_a += a;
+            sum_b += b;
+            sum_aa += a * a;
+            sum_bb += b * b;
+            sum_ab += a * b;
+        }
+    }
+    let n = f64::from(width * height);
+    let (mean_a, mean_b) = (sum_a / n, sum_b / n);
+    let var_a = sum_aa / n - mean_a * mean_a;
+    let var_b = sum_bb / n - mean_b * mean_b;
+    let covariance = sum_ab / n - mean_a * mean_b;
+    ((2.0 * mean_a * mean_b + C1) * (2.0 * covariance + C2))
+        / ((mean_a * mean_a + mean_b * mean_b + C1) * (var_a + var_b + C2))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ssim, Quality, Ssim};
+    use image::{DynamicImage, GrayImage, Luma};
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn parse() {
+        assert_eq!("auto".parse::<Quality>(), Ok(Quality::Auto));
+        assert_eq!("80".parse::<Quality>(), Ok(Quality::Exact(80)));
+        assert_eq!("100".parse::<Quality>(), Ok(Quality::Exact(100)));
+        assert!("101".parse::<Quality>().is_err());
+        assert!("high".parse::<Quality>().is_err());
+
+        assert_eq!("0.985".parse::<Ssim>().unwrap().to_string(), "0.9850");
+        assert_eq!("1".parse::<Ssim>().unwrap().value(), 1.0);
+        assert!("1.5".parse::<Ssim>().is_err());
+    }
+
+    #[test]
+    fn similarity() {
+        let gradient = |offset: u8| {
+            DynamicImage::ImageLuma8(GrayImage::from_fn(32, 32, |x, y| {
+                // coordinates are small
+                #[allow(clippy::cast_possible_truncation)]
+                let value = (x * 4 + y * 2) as u8;
+                Luma([value.saturating_add(offset)])
+            }))
+        };
+        let noise = DynamicImage::ImageLuma8(GrayImage::from_fn(32, 32, |x, y| {
+            Luma([if (x + y) % 2 == 0 { 0 } else { 255 }])
+        }));
+        assert_eq!(ssim(&gradient(0), &gradient(0)), 1.0);
+        let shifted = ssim(&gradient(0), &gradient(4));
+        assert!(shifted < 1.0 && shifted > 0.95, "{}", shifted);
+        assert!(ssim(&gradient(0), &noise) < 0.5);
+
+        let tiny = DynamicImage::ImageLuma8(GrayImage::new(3, 2));
+        assert_eq!(ssim(&tiny, &tiny), 1.0);
+    }
+}
@@ -0,0 +1,292 @@
+use super::bounds::{Rect, Size};
+use image::{DynamicImage, GenericImageView};
+
+/// Longest side of the downscaled image used for the analysis.
+const ANALYSIS_SIZE: u32 = 256;
+/// Side length of the blocks used to compute the local entropy.
+const ENTROPY_BLOCK_SIZE: u32 = 8;
+
+const EDGE_WEIGHT: f64 = 1.0;
+const SKIN_WEIGHT: f64 = 1.8;
+const SATURATION_WEIGHT: f64 = 0.3;
+const ENTROPY_WEIGHT: f64 = 0.4;
+/// Relative penalty for windows at the image border compared to centered ones.
+const CENTER_BIAS: f64 = 0.1;
+
+/// Normalized reference skin color.
+const SKIN_COLOR: [f64; 3] = [0.78, 0.57, 0.44];
+
+#[inline]
+fn luma([r, g, b]: [f64; 3]) -> f64 {
+    0.2126 * r + 0.7152 * g + 0.0722 * b
+}
+
+#[inline]
+fn saturation([r, g, b]: [f64; 3]) -> f64 {
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    if max <= 0.0 {
+        0.0
+    } else {
+        (max - min) / max
+    }
+}
+
+#[inline]
+fn skin(rgb: [f64; 3]) -> f64 {
+    let mag = rgb.iter().map(|c| c * c).sum::<f64>().sqrt();
+    if mag <= 0.0 {
+        return 0.0;
+    }
+    let skin_mag = SKIN_COLOR.iter().map(|c| c * c).sum::<f64>().sqrt();
+    let dist = rgb
+        .iter()
+        .zip(SKIN_COLOR.iter())
+        .map(|(c, s)| (c / mag - s / skin_mag).powi(2))
+        .sum::<f64>()
+        .sqrt();
+    (1.0 - dist * 4.0).max(0.0)
+}
+
+/// Per pixel saliency of the image.
+struct Saliency {
+    width: u32,
+    height: u32,
+    scores: Vec<f64>,
+}
+
+impl Saliency {
+    fn new(image: &image::RgbImage) -> Self {
+        let (width, height) = image.dimensions();
+        let rgb = |x: u32, y: u32| image.get_pixel(x, y).0.map(|c| f64::from(c) / 255.0);
+        let lumas: Vec<f64> = image
+            .pixels()
+            .map(|p| luma(p.0.map(f64::from)) / 255.0)
+            .collect();
+        let entropy = block_entropy(&lumas, width, height);
+
+        let idx = |x: u32, y: u32| (y * width + x) as usize;
+        let mut scores = Vec::with_capacity(lumas.len());
+        for y in 0..height {
+            for x in 0..width {
+                let l = lumas[idx(x, y)];
+                let neighbors = [
+                    lumas[idx(x.saturating_sub(1), y)],
+                    lumas[idx((x + 1).min(width - 1), y)],
+                    lumas[idx(x, y.saturating_sub(1))],
+                    lumas[idx(x, (y + 1).min(height - 1))],
+                ];
+                let edge = (4.0 * l - neighbors.iter().sum::<f64>()).abs().min(1.0);
+                let rgb = rgb(x, y);
+                let skin = if (0.2..=0.9).contains(&l) {
+                    skin(rgb)
+                } else {
+                    0.0
+                };
+                let saturation = if (0.05..=0.9).contains(&l) {
+                    saturation(rgb)
+                } else {
+                    0.0
+                };
+                scores.push(
+                    EDGE_WEIGHT * edge
+                        + SKIN_WEIGHT * skin
+                        + SATURATION_WEIGHT * saturation
+                        + ENTROPY_WEIGHT * entropy[idx(x, y)],
+                );
+            }
+        }
+        Self {
+            width,
+            height,
+            scores,
+        }
+    }
+
+    /// Summed area table with an additional leading row and column of zeros.
+    fn integral(&self) -> Vec<f64> {
+        let stride = self.width as usize + 1;
+        let mut table = vec![0.0; stride * (self.height as usize + 1)];
+        for y in 0..self.height as usize {
+            let mut row = 0.0;
+            for x in 0..self.width as usize {
+                row += self.scores[y * self.width as usize + x];
+                table[(y + 1) * stride + x + 1] = table[y * stride + x + 1] + row;
+            }
+        }
+        table
+    }
+}
+
+/// Normalized shannon entropy of the luma histogram of each block, per pixel.
+fn block_entropy(lumas: &[f64], width: u32, height: u32) -> Vec<f64> {
+    const BINS: usize = 16;
+    let mut entropy = vec![0.0; lumas.len()];
+    for by in (0..height).step_by(ENTROPY_BLOCK_SIZE as usize) {
+        for bx in (0..width).step_by(ENTROPY_BLOCK_SIZE as usize) {
+            let xs = bx..(bx + ENTROPY_BLOCK_SIZE).min(width);
+            let ys = by..(by + ENTROPY_BLOCK_SIZE).min(height);
+            let pixels = || {
+                ys.clone()
+                    .flat_map(|y| xs.clone().map(move |x| (y * width + x) as usize))
+            };
+            let mut histogram = [0_u32; BINS];
+            let mut count = 0_u32;
+            for i in pixels() {
+                // luma is normalized, hence the cast cannot truncate
+                #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+                let bin = ((lumas[i] * BINS as f64) as usize).min(BINS - 1);
+                histogram[bin] += 1;
+                count += 1;
+            }
+            let value = histogram
+                .iter()
+                .filter(|&&n| n > 0)
+                .map(|&n| {
+                    let p = f64::from(n) / f64::from(count);
+                    -p * p.log2()
+                })
+                .sum::<f64>()
+                / (BINS as f64).log2();
+            for i in pixels() {
+                entropy[i] = value;
+            }
+        }
+    }
+    entropy
+}
+
+/// Finds the most salient crop window of the given size.
+///
+/// The image is scored on a downscaled copy based on edge energy, local entropy,
+/// saturation and skin tones. The result is deterministic for the same input.
+#[must_use]
+pub fn window(image: &DynamicImage, size: Size) -> Rect {
+    let (width, height) = image.dimensions();
+    let size = Size {
+        width: size.width.min(width),
+        height: size.height.min(height),
+    };
+    let centered = Rect {
+        x: (width - size.width) / 2,
+        y: (height - size.height) / 2,
+        width: size.width,
+        height: size.height,
+    };
+    if size.width == width && size.height == height {
+        return centered;
+    }
+
+    let thumbnail = image.thumbnail(ANALYSIS_SIZE, ANALYSIS_SIZE).into_rgb8();
+    let saliency = Saliency::new(&thumbnail);
+    let table = saliency.integral();
+    let stride = saliency.width as usize + 1;
+    let area = |x: usize, y: usize, w: usize, h: usize| {
+        table[(y + h) * stride + x + w] - table[y * stride + x + w] - table[(y + h) * stride + x]
+            + table[y * stride + x]
+    };
+
+    let scale_x = f64::from(saliency.width) / f64::from(width);
+    let scale_y = f64::from(saliency.height) / f64::from(height);
+    // analysis coordinates are bounded by the thumbnail, hence the casts cannot truncate
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let to_analysis = |len: u32, scale: f64, max: u32| {
+        ((f64::from(len) * scale).round() as u32).clamp(1, max) as usize
+    };
+    let window_w = to_analysis(size.width, scale_x, saliency.width);
+    let window_h = to_analysis(size.height, scale_y, saliency.height);
+    let free_x = saliency.width as usize - window_w;
+    let free_y = saliency.height as usize - window_h;
+
+    let mut best = None;
+    for y in 0..=free_y {
+        for x in 0..=free_x {
+            let offset = |pos: usize, free: usize| {
+                if free == 0 {
+                    0.0
+                } else {
+                    (pos as f64 / free as f64 - 0.5).abs() * 2.0
+                }
+            };
+            let bias = 1.0 - CENTER_BIAS * offset(x, free_x).max(offset(y, free_y));
+            let score = area(x, y, window_w, window_h) * bias;
+            // strictly greater keeps the first best window for determinism
+            let better = match best {
+                Some((best, _, _)) => score > best,
+                None => true,
+            };
+            if better {
+                best = Some((score, x, y));
+            }
+        }
+    }
+
+    match best {
+        Some((_, x, y)) => {
+            // offsets are bounded by the image, hence the casts cannot truncate
+            #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+            let from_analysis =
+                |pos: usize, scale: f64, max: u32| ((pos as f64 / scale).round() as u32).min(max);
+            Rect {
+                x: from_analysis(x, scale_x, width - size.width),
+                y: from_analysis(y, scale_y, height - size.height),
+                width: size.width,
+                height: size.height,
+            }
+        }
+        None => centered,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::window;
+    use crate::bounds::{Rect, Size};
+    use image::{DynamicImage, Rgb, RgbImage};
+    use pretty_assertions::assert_eq;
+
+    fn with_detail_at(detail_x: u32) -> DynamicImage {
+        let image = RgbImage::from_fn(400, 200, |x, y| {
+            if (detail_x..detail_x + 100).contains(&x) && (x + y) % 2 == 0 {
+                Rgb([200, 40, 40])
+            } else {
+                Rgb([20, 20, 20])
+            }
+        });
+        DynamicImage::ImageRgb8(image)
+    }
+
+    #[test]
+    fn follows_detail() {
+        let size = Size {
+            width: 200,
+            height: 200,
+        };
+        let left = window(&with_detail_at(0), size);
+        assert_eq!(left.x, 0);
+        let right = window(&with_detail_at(300), size);
+        assert_eq!(right.x, 200);
+    }
+
+    #[test]
+    fn uniform_is_centered() {
+        let image = DynamicImage::ImageRgb8(RgbImage::from_pixel(300, 100, Rgb([128; 3])));
+        let size = Size {
+            width: 100,
+            height: 100,
+        };
+        let Rect { x, y, .. } = window(&image, size);
+        assert!((99..=101).contains(&x));
+        assert_eq!(y, 0);
+    }
+
+    #[test]
+    fn deterministic() {
+        let image = with_detail_at(150);
+        let size = Size {
+            width: 120,
+            height: 200,
+        };
+        assert_eq!(window(&image, size), window(&image, size));
+    }
+}
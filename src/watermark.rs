@@ -0,0 +1,357 @@
+use super::bounds::Gravity;
+use super::params::{deserialize_from_str, Hundredths};
+use image::{imageops, DynamicImage, GenericImageView};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Longest accepted watermark id.
+const MAX_ID_LEN: usize = 32;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("image error: `{0}`")]
+    Image(#[from] image::error::ImageError),
+
+    #[error("invalid watermark id `{0}`")]
+    InvalidId(String),
+
+    #[error("invalid watermark `{0}`")]
+    InvalidSpec(String),
+
+    #[error("unknown watermark `{0}`")]
+    Unknown(WatermarkId),
+}
+
+/// Name of a watermark configured on the server.
+///
+/// Ids consist of up to 32 ASCII letters, digits, `-` and `_` and are stored
+/// inline, so that they can be part of cache keys.
+#[derive(Eq, PartialEq, Hash, Clone, Copy)]
+pub struct WatermarkId {
+    len: u8,
+    bytes: [u8; MAX_ID_LEN],
+}
+
+impl WatermarkId {
+    #[inline]
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        // only ASCII characters are accepted
+        std::str::from_utf8(&self.bytes[..usize::from(self.len)]).unwrap_or_default()
+    }
+}
+
+impl std::fmt::Display for WatermarkId {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::fmt::Debug for WatermarkId {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:?}", self.as_str())
+    }
+}
+
+impl std::str::FromStr for WatermarkId {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let valid = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_';
+        if s.is_empty() || s.len() > MAX_ID_LEN || !s.chars().all(valid) {
+            return Err(Error::InvalidId(s.to_string()));
+        }
+        let mut bytes = [0; MAX_ID_LEN];
+        bytes[..s.len()].copy_from_slice(s.as_bytes());
+        // the length is at most 32
+        #[allow(clippy::cast_possible_truncation)]
+        let len = s.len() as u8;
+        Ok(Self { len, bytes })
+    }
+}
+
+deserialize_from_str!(WatermarkId);
+
+/// Placement of a watermark on the output image.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Placement {
+    /// corner or edge the watermark is anchored to
+    pub gravity: Gravity,
+    /// distance to the edges of the image in pixels
+    pub margin: u32,
+    /// opacity from 0 (invisible) to 1
+    pub opacity: f32,
+    /// width of the watermark relative to the width of the image
+    pub scale: Option<f32>,
+}
+
+impl Default for Placement {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            gravity: Gravity::SouthEast,
+            margin: 0,
+            opacity: 1.0,
+            scale: None,
+        }
+    }
+}
+
+/// Configuration of a watermark in the form
+/// `id=path[;gravity=..][;margin=..][;opacity=..][;scale=..]`.
+///
+/// # Example
+///
+/// ```
+/// use imop::watermark::WatermarkSpec;
+///
+/// let spec: WatermarkSpec = "logo=logo.png;gravity=south-west;margin=8;opacity=0.5"
+///     .parse()
+///     .unwrap();
+/// assert_eq!(spec.id.as_str(), "logo");
+/// assert_eq!(spec.placement.margin, 8);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct WatermarkSpec {
+    pub id: WatermarkId,
+    /// path of the image relative to the root
+    pub path: String,
+    pub placement: Placement,
+}
+
+impl std::str::FromStr for WatermarkSpec {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || Error::InvalidSpec(s.to_string());
+        let mut parts = s.split(';');
+        let (id, path) = parts
+            .next()
+            .and_then(|source| source.split_once('='))
+            .ok_or_else(invalid)?;
+        let mut placement = Placement::default();
+        for part in parts {
+            let (key, value) = part.split_once('=').ok_or_else(invalid)?;
+            match key.trim() {
+                "gravity" => placement.gravity = value.parse().map_err(|_| invalid())?,
+                "margin" => placement.margin = value.trim().parse().map_err(|_| invalid())?,
+                "opacity" => {
+                    let opacity =
+                        Hundredths::parse(value, "opacity", 1.0).map_err(|_| invalid())?;
+                    placement.opacity = opacity.value();
+                }
+                "scale" => {
+                    let scale = Hundredths::parse(value, "scale", 1.0).map_err(|_| invalid())?;
+                    placement.scale = Some(scale.value()).filter(|&scale| scale > 0.0);
+                }
+                _ => return Err(invalid()),
+            }
+        }
+        Ok(Self {
+            id: id.trim().parse()?,
+            path: path.trim().to_string(),
+            placement,
+        })
+    }
+}
+
+/// Image composited onto output images.
+#[derive(Debug, Clone)]
+pub struct Watermark {
+    pub image: DynamicImage,
+    pub placement: Placement,
+}
+
+impl Watermark {
+    /// Composites the watermark onto the image.
+    #[must_use]
+    pub fn apply(&self, mut image: DynamicImage) -> DynamicImage {
+        let Placement {
+            gravity,
+            margin,
+            opacity,
+            scale,
+        } = self.placement;
+        let (width, height) = image.dimensions();
+        // margins are configured freely and may exceed the image
+        let available_width = width.saturating_sub(margin.saturating_mul(2));
+        let available_height = height.saturating_sub(margin.saturating_mul(2));
+        if available_width == 0 || available_height == 0 {
+            return image;
+        }
+
+        let mut overlay = match scale {
+            // dimensions are far below the precision limits of f32
+            #[allow(
+                clippy::cast_precision_loss,
+                clippy::cast_possible_truncation,
+                clippy::cast_sign_loss
+            )]
+            Some(scale) => {
+                let target = ((width as f32 * scale).round() as u32).max(1);
+                self.image
+                    .resize(target, u32::MAX, imageops::FilterType::Lanczos3)
+            }
+            None => self.image.clone(),
+        };
+        // watermarks never exceed the available area
+        if overlay.width() > available_width || overlay.height() > available_height {
+            overlay = overlay.resize(
+                available_width,
+                available_height,
+                imageops::FilterType::Lanczos3,
+            );
+        }
+        let mut overlay = overlay.into_rgba8();
+        if opacity < 1.0 {
+            for pixel in overlay.pixels_mut() {
+                // the product is in range
+                #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+                let alpha = (f32::from(pixel[3]) * opacity).round() as u8;
+                pixel[3] = alpha;
+            }
+        }
+
+        let point = gravity.focal_point();
+        let offset = |available: u32, size: u32, relative: f32| {
+            // offsets are bounded by the image dimensions
+            #[allow(
+                clippy::cast_precision_loss,
+                clippy::cast_possible_truncation,
+                clippy::cast_sign_loss
+            )]
+            let offset = ((available - size) as f32 * relative).round() as u32;
+            i64::from(margin.saturating_add(offset))
+        };
+        let x = offset(available_width, overlay.width(), point.x.value());
+        let y = offset(available_height, overlay.height(), point.y.value());
+        imageops::overlay(&mut image, &overlay, x, y);
+        image
+    }
+}
+
+/// Watermarks configured on the server, selectable by their id.
+#[derive(Debug, Clone, Default)]
+pub struct Watermarks(HashMap<WatermarkId, Watermark>);
+
+impl Watermarks {
+    /// Loads the watermark images relative to the root directory.
+    ///
+    /// # Errors
+    ///
+    /// If an image cannot be read or decoded.
+    pub fn load(root: &Path, specs: &[WatermarkSpec]) -> Result<Self, Error> {
+        let mut watermarks = HashMap::new();
+        for spec in specs {
+            let image = image::open(root.join(&spec.path))?;
+            let watermark = Watermark {
+                image,
+                placement: spec.placement,
+            };
+            watermarks.insert(spec.id, watermark);
+        }
+        Ok(Self(watermarks))
+    }
+
+    #[inline]
+    pub fn insert(&mut self, id: WatermarkId, watermark: Watermark) {
+        self.0.insert(id, watermark);
+    }
+
+    /// Returns the watermark with the given id.
+    ///
+    /// # Errors
+    ///
+    /// If no watermark with the id is configured.
+    #[inline]
+    pub fn get(&self, id: WatermarkId) -> Result<&Watermark, Error> {
+        self.0.get(&id).ok_or(Error::Unknown(id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Placement, Watermark, WatermarkId, WatermarkSpec, Watermarks};
+    use crate::bounds::Gravity;
+    use image::{DynamicImage, GenericImageView, Rgba, RgbaImage};
+    use pretty_assertions::assert_eq;
+
+    fn watermark(placement: Placement) -> Watermark {
+        Watermark {
+            image: DynamicImage::ImageRgba8(RgbaImage::from_pixel(4, 2, Rgba([255, 0, 0, 255]))),
+            placement,
+        }
+    }
+
+    fn white(width: u32, height: u32) -> DynamicImage {
+        DynamicImage::ImageRgba8(RgbaImage::from_pixel(width, height, Rgba([255; 4])))
+    }
+
+    #[test]
+    fn parse() {
+        assert!("logo".parse::<WatermarkId>().is_ok());
+        assert!("../logo.png".parse::<WatermarkId>().is_err());
+        assert!("".parse::<WatermarkId>().is_err());
+
+        let spec: WatermarkSpec = "logo=img/logo.png;gravity=north;opacity=0.25;scale=0.5"
+            .parse()
+            .unwrap();
+        assert_eq!(spec.path, "img/logo.png");
+        assert_eq!(
+            spec.placement,
+            Placement {
+                gravity: Gravity::North,
+                margin: 0,
+                opacity: 0.25,
+                scale: Some(0.5),
+            }
+        );
+        assert!("logo".parse::<WatermarkSpec>().is_err());
+        assert!("logo=logo.png;size=2".parse::<WatermarkSpec>().is_err());
+    }
+
+    #[test]
+    fn placement() {
+        let placement = Placement {
+            margin: 1,
+            ..Placement::default()
+        };
+        let image = watermark(placement).apply(white(10, 6));
+        // bottom right corner, one pixel margin
+        assert_eq!(image.get_pixel(8, 4), Rgba([255, 0, 0, 255]));
+        assert_eq!(image.get_pixel(5, 3), Rgba([255, 0, 0, 255]));
+        assert_eq!(image.get_pixel(9, 5), Rgba([255; 4]));
+        assert_eq!(image.get_pixel(4, 3), Rgba([255; 4]));
+        assert_eq!(image.get_pixel(5, 2), Rgba([255; 4]));
+
+        // margins larger than the image leave it untouched
+        let placement = Placement {
+            margin: u32::MAX,
+            ..Placement::default()
+        };
+        assert_eq!(watermark(placement).apply(white(10, 6)), white(10, 6));
+    }
+
+    #[test]
+    fn opacity_and_scale() {
+        let placement = Placement {
+            gravity: Gravity::NorthWest,
+            opacity: 0.5,
+            scale: Some(0.8),
+            ..Placement::default()
+        };
+        let image = watermark(placement).apply(white(10, 10));
+        assert_eq!(image.get_pixel(7, 3).0[..3], [255, 127, 127]);
+        assert_eq!(image.get_pixel(8, 0), Rgba([255; 4]));
+        assert_eq!(image.get_pixel(0, 4), Rgba([255; 4]));
+    }
+
+    #[test]
+    fn unknown() {
+        let mut watermarks = Watermarks::default();
+        let id: WatermarkId = "logo".parse().unwrap();
+        watermarks.insert(id, watermark(Placement::default()));
+        assert!(watermarks.get(id).is_ok());
+        assert!(watermarks.get("other".parse().unwrap()).is_err());
+    }
+}